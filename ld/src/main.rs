@@ -0,0 +1,106 @@
+//! The dynamic loader `/lib/ld.so`, which the kernel starts in place of programs asking for it
+//! with `PT_INTERP`. It loads the libraries they need from `/lib`, binds their symbols and
+//! jumps to `_start`, which programs linked against `libuser_lib.so` take from it.
+
+#![no_std]
+#![no_main]
+#![feature(asm)]
+#![feature(global_asm)]
+
+mod object;
+mod syscall;
+
+use object::Object;
+
+/// Most objects in a process, the program included.
+const MAX_OBJECTS: usize = 8;
+
+const STDERR: usize = 2;
+/// What a program the loader can not start exits with.
+const EXIT_LOAD_FAILED: i32 = 127;
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHNUM: usize = 5;
+
+// Entered like a program, with argc, argv, envp and auxv on the stack.
+global_asm!("\
+.globl _start
+_start:
+    movq %rsp, %rdi
+    andq $-16, %rsp
+    call loader_main
+");
+
+/// Report why the program can not be started and exit.
+pub fn fail(message: &str, name: &[u8]) -> ! {
+    for part in [&b"ld.so: "[..], message.as_bytes(), b" ", name, b"\n"].iter() {
+        let _ = syscall::write(STDERR, part);
+    }
+    syscall::exit(EXIT_LOAD_FAILED);
+}
+
+#[no_mangle]
+unsafe extern "C" fn loader_main(stack: *const usize) -> ! {
+    let argc = *stack;
+    let mut auxv = stack.add(argc + 2);
+    while *auxv != 0 {
+        auxv = auxv.add(1);
+    }
+    auxv = auxv.add(1);
+    let (mut program_headers, mut program_header_count) = (0, 0);
+    while *auxv != AT_NULL {
+        match *auxv {
+            AT_PHDR => program_headers = *auxv.add(1),
+            AT_PHNUM => program_header_count = *auxv.add(1),
+            _ => {}
+        }
+        auxv = auxv.add(2);
+    }
+
+    // The program comes first, then the libraries breadth first, which is the order symbols
+    // are looked up in.
+    let mut objects = [Object::EMPTY; MAX_OBJECTS];
+    objects[0] = Object::program(program_headers, program_header_count);
+    let mut count = 1;
+    let mut next = 0;
+    while next < count {
+        let object = objects[next];
+        for name in object.needed() {
+            if objects[..count].iter().any(|loaded| loaded.name == name) {
+                continue;
+            }
+            if count == MAX_OBJECTS {
+                fail("too many libraries, cannot load", name);
+            }
+            objects[count] = Object::load(name);
+            count += 1;
+        }
+        next += 1;
+    }
+    let objects = &objects[..count];
+    for object in objects {
+        object.relocate(objects);
+    }
+    for object in objects {
+        object.protect();
+    }
+
+    let entry = match objects.iter().find_map(|object| object.lookup(b"_start")) {
+        Some(entry) => entry,
+        None => fail("no entry point", b"_start"),
+    };
+    // The program finds the stack as the kernel left it.
+    asm!(
+        "mov rsp, {}",
+        "jmp {}",
+        in(reg) stack,
+        in(reg) entry,
+        options(noreturn),
+    );
+}
+
+#[panic_handler]
+fn panic_handler(_panic_info: &core::panic::PanicInfo) -> ! {
+    fail("panicked", b"");
+}
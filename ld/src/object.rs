@@ -0,0 +1,407 @@
+use crate::fail;
+use crate::syscall::*;
+
+const PAGE_SIZE: usize = 4096;
+/// Most program headers of an object.
+const MAX_PROGRAM_HEADERS: usize = 16;
+/// Most libraries an object needs directly.
+const MAX_NEEDED: usize = 8;
+/// Where libraries are looked for.
+const LIBRARY_DIR: &[u8] = b"/lib/";
+const PATH_MAX: usize = 256;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 0x3e;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_PHDR: u32 = 6;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+const DT_NULL: usize = 0;
+const DT_NEEDED: usize = 1;
+const DT_PLTRELSZ: usize = 2;
+const DT_HASH: usize = 4;
+const DT_STRTAB: usize = 5;
+const DT_SYMTAB: usize = 6;
+const DT_RELA: usize = 7;
+const DT_RELASZ: usize = 8;
+const DT_JMPREL: usize = 23;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_GLOB_DAT: u32 = 6;
+const R_X86_64_JUMP_SLOT: u32 = 7;
+const R_X86_64_RELATIVE: u32 = 8;
+
+const SHN_UNDEF: u16 = 0;
+const STB_LOCAL: u8 = 0;
+const STB_WEAK: u8 = 2;
+
+#[repr(C)]
+#[derive(Default)]
+struct FileHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    ph_offset: u64,
+    sh_offset: u64,
+    flags: u32,
+    header_size: u16,
+    ph_entry_size: u16,
+    ph_count: u16,
+    sh_entry_size: u16,
+    sh_count: u16,
+    sh_string_index: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    file_size: u64,
+    mem_size: u64,
+    align: u64,
+}
+
+#[repr(C)]
+struct Symbol {
+    name: u32,
+    info: u8,
+    other: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+}
+
+#[repr(C)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+impl ProgramHeader {
+    const EMPTY: Self = Self { kind: 0, flags: 0, offset: 0, vaddr: 0, paddr: 0, file_size: 0, mem_size: 0, align: 0 };
+
+    fn prot(&self) -> usize {
+        let mut prot = 0;
+        if self.flags & PF_R != 0 {
+            prot |= PROT_READ;
+        }
+        if self.flags & PF_W != 0 {
+            prot |= PROT_WRITE;
+        }
+        if self.flags & PF_X != 0 {
+            prot |= PROT_EXEC;
+        }
+        prot
+    }
+}
+
+/// The program or a shared library, with the addresses its dynamic section gives moved by
+/// `bias`, where it is loaded.
+#[derive(Copy, Clone)]
+pub struct Object {
+    /// Name other objects need it by, empty for the program.
+    pub name: &'static [u8],
+    bias: usize,
+    program_headers: [ProgramHeader; MAX_PROGRAM_HEADERS],
+    program_header_count: usize,
+    /// Whether the loader mapped it, rather than the kernel.
+    mapped: bool,
+    hash: usize,
+    strtab: usize,
+    symtab: usize,
+    rela: (usize, usize),
+    jmprel: (usize, usize),
+    /// Offsets in `strtab` of the names of the libraries it needs.
+    needed: [usize; MAX_NEEDED],
+    needed_count: usize,
+}
+
+impl Object {
+    pub const EMPTY: Self = Self {
+        name: b"",
+        bias: 0,
+        program_headers: [ProgramHeader::EMPTY; MAX_PROGRAM_HEADERS],
+        program_header_count: 0,
+        mapped: false,
+        hash: 0,
+        strtab: 0,
+        symtab: 0,
+        rela: (0, 0),
+        jmprel: (0, 0),
+        needed: [0; MAX_NEEDED],
+        needed_count: 0,
+    };
+
+    /// The program the kernel loaded, whose `count` program headers are at `addr`.
+    pub fn program(addr: usize, count: usize) -> Self {
+        if count > MAX_PROGRAM_HEADERS {
+            fail("too many program headers", b"");
+        }
+        let mut object = Self::EMPTY;
+        let headers = unsafe { core::slice::from_raw_parts(addr as *const ProgramHeader, count) };
+        object.program_headers[..count].copy_from_slice(headers);
+        object.program_header_count = count;
+        let phdr = match headers.iter().find(|ph| ph.kind == PT_PHDR) {
+            Some(phdr) => phdr,
+            None => fail("program headers are not loaded", b""),
+        };
+        object.bias = addr.wrapping_sub(phdr.vaddr as usize);
+        object.read_dynamic();
+        object
+    }
+
+    /// Map the library `name` from `LIBRARY_DIR`.
+    pub fn load(name: &'static [u8]) -> Self {
+        let mut path = [0u8; PATH_MAX];
+        if LIBRARY_DIR.len() + name.len() >= PATH_MAX {
+            fail("library name too long", name);
+        }
+        path[..LIBRARY_DIR.len()].copy_from_slice(LIBRARY_DIR);
+        path[LIBRARY_DIR.len()..LIBRARY_DIR.len() + name.len()].copy_from_slice(name);
+        let fd = match open(&path, O_RDONLY) {
+            Ok(fd) => fd,
+            Err(_) => fail("cannot open shared library", name),
+        };
+
+        let mut header = FileHeader::default();
+        read_at(fd, 0, as_bytes_mut(&mut header), name);
+        let count = header.ph_count as usize;
+        if header.ident[..4] != ELF_MAGIC
+            || header.ident[4] != ELFCLASS64
+            || header.ident[5] != ELFDATA2LSB
+            || header.kind != ET_DYN
+            || header.machine != EM_X86_64
+            || header.ph_entry_size as usize != core::mem::size_of::<ProgramHeader>()
+            || count > MAX_PROGRAM_HEADERS
+        {
+            fail("not a shared library", name);
+        }
+        let mut object = Self::EMPTY;
+        object.name = name;
+        object.program_header_count = count;
+        object.mapped = true;
+        read_at(fd, header.ph_offset as usize, as_bytes_mut(&mut object.program_headers[..count]), name);
+
+        // Room for every segment is taken at once, then filled from the file.
+        let start = object.segments().map(|ph| page_down(ph.vaddr as usize)).min();
+        let end = object.segments().map(|ph| page_up((ph.vaddr + ph.mem_size) as usize)).max();
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => fail("no loadable segment", name),
+        };
+        let base = match mmap(0, end - start, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0) {
+            Ok(base) => base,
+            Err(_) => fail("out of memory for", name),
+        };
+        object.bias = base - start;
+        for ph in object.segments() {
+            if ph.file_size > ph.mem_size {
+                fail("bad segment in", name);
+            }
+            let data = unsafe {
+                core::slice::from_raw_parts_mut((object.bias + ph.vaddr as usize) as *mut u8, ph.file_size as usize)
+            };
+            read_at(fd, ph.offset as usize, data, name);
+        }
+        let _ = close(fd);
+        object.read_dynamic();
+        object
+    }
+
+    fn headers(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers[..self.program_header_count].iter()
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.headers().filter(|ph| ph.kind == PT_LOAD && ph.mem_size > 0)
+    }
+
+    fn read_dynamic(&mut self) {
+        let dynamic = match self.headers().find(|ph| ph.kind == PT_DYNAMIC) {
+            Some(dynamic) => self.bias + dynamic.vaddr as usize,
+            None => fail("no dynamic section in", self.name),
+        };
+        let mut entry = dynamic as *const [usize; 2];
+        loop {
+            let [tag, value] = unsafe { *entry };
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED if self.needed_count == MAX_NEEDED => fail("too many libraries needed by", self.name),
+                DT_NEEDED => {
+                    self.needed[self.needed_count] = value;
+                    self.needed_count += 1;
+                }
+                DT_HASH => self.hash = self.bias + value,
+                DT_STRTAB => self.strtab = self.bias + value,
+                DT_SYMTAB => self.symtab = self.bias + value,
+                DT_RELA => self.rela.0 = self.bias + value,
+                DT_RELASZ => self.rela.1 = value,
+                DT_JMPREL => self.jmprel.0 = self.bias + value,
+                DT_PLTRELSZ => self.jmprel.1 = value,
+                _ => {}
+            }
+            entry = unsafe { entry.add(1) };
+        }
+        if self.hash == 0 || self.strtab == 0 || self.symtab == 0 {
+            fail("no symbol table in", self.name);
+        }
+    }
+
+    /// Names of the libraries it needs.
+    pub fn needed(&self) -> impl Iterator<Item = &'static [u8]> + '_ {
+        self.needed[..self.needed_count].iter().map(move |&offset| c_str(self.strtab + offset))
+    }
+
+    fn symbol(&self, index: usize) -> &'static Symbol {
+        unsafe { &*((self.symtab + index * core::mem::size_of::<Symbol>()) as *const Symbol) }
+    }
+
+    /// Address of the symbol `name` if the object defines it, through its `DT_HASH` table.
+    pub fn lookup(&self, name: &[u8]) -> Option<usize> {
+        let hash = self.hash as *const u32;
+        let bucket_count = unsafe { *hash } as usize;
+        let buckets = unsafe { hash.add(2) };
+        let chains = unsafe { buckets.add(bucket_count) };
+        let mut index = unsafe { *buckets.add(elf_hash(name) as usize % bucket_count) } as usize;
+        while index != 0 {
+            let symbol = self.symbol(index);
+            if symbol.section != SHN_UNDEF
+                && symbol.info >> 4 != STB_LOCAL
+                && c_str(self.strtab + symbol.name as usize) == name
+            {
+                return Some(self.bias + symbol.value as usize);
+            }
+            index = unsafe { *chains.add(index) } as usize;
+        }
+        None
+    }
+
+    /// Value of the symbol `index` refers to, searched in `objects` in order.
+    fn resolve(&self, index: usize, objects: &[Object]) -> usize {
+        let symbol = self.symbol(index);
+        if symbol.info >> 4 == STB_LOCAL {
+            return self.bias + symbol.value as usize;
+        }
+        let name = c_str(self.strtab + symbol.name as usize);
+        match objects.iter().find_map(|object| object.lookup(name)) {
+            Some(addr) => addr,
+            // Weak references to nothing are null.
+            None if symbol.info >> 4 == STB_WEAK => 0,
+            None => fail("undefined symbol", name),
+        }
+    }
+
+    /// Apply every relocation of the object, binding its symbols to the first of `objects`
+    /// defining them.
+    pub fn relocate(&self, objects: &[Object]) {
+        for &(table, size) in [self.rela, self.jmprel].iter() {
+            let count = size / core::mem::size_of::<Rela>();
+            let relas = unsafe { core::slice::from_raw_parts(table as *const Rela, count) };
+            for rela in relas {
+                let kind = rela.info as u32;
+                let index = (rela.info >> 32) as usize;
+                let addend = rela.addend as usize;
+                let value = match kind {
+                    R_X86_64_NONE => continue,
+                    R_X86_64_RELATIVE => self.bias.wrapping_add(addend),
+                    R_X86_64_64 => self.resolve(index, objects).wrapping_add(addend),
+                    R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => self.resolve(index, objects),
+                    _ => fail("unsupported relocation in", self.name),
+                };
+                unsafe { *((self.bias + rela.offset as usize) as *mut usize) = value };
+            }
+        }
+    }
+
+    /// Give the pages the loader mapped the protection of their segments, with those of
+    /// both when two share a page, and make the relocated data read-only.
+    pub fn protect(&self) {
+        if self.mapped {
+            let start = self.segments().map(|ph| page_down(ph.vaddr as usize)).min().unwrap();
+            let end = self.segments().map(|ph| page_up((ph.vaddr + ph.mem_size) as usize)).max().unwrap();
+            let mut run = (start, self.page_prot(start));
+            for page in (start..=end).step_by(PAGE_SIZE) {
+                let prot = if page == end { usize::MAX } else { self.page_prot(page) };
+                if prot != run.1 {
+                    let _ = mprotect(self.bias + run.0, page - run.0, run.1);
+                    run = (page, prot);
+                }
+            }
+        }
+        for relro in self.headers().filter(|ph| ph.kind == PT_GNU_RELRO) {
+            let start = page_down(self.bias + relro.vaddr as usize);
+            let end = page_down(self.bias + (relro.vaddr + relro.mem_size) as usize);
+            if end > start {
+                let _ = mprotect(start, end - start, PROT_READ);
+            }
+        }
+    }
+
+    /// Protection of the segments lying in the page at `addr`.
+    fn page_prot(&self, addr: usize) -> usize {
+        self.segments()
+            .filter(|ph| page_down(ph.vaddr as usize) <= addr && addr < (ph.vaddr + ph.mem_size) as usize)
+            .fold(0, |prot, ph| prot | ph.prot())
+    }
+}
+
+/// The `ELF` hash function of the System V ABI, which `DT_HASH` tables use.
+fn elf_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0u32, |hash, &byte| {
+        let hash = (hash << 4).wrapping_add(byte as u32);
+        let high = hash & 0xf000_0000;
+        (hash ^ (high >> 24)) & !high
+    })
+}
+
+/// The NUL-terminated string at `addr`, without its NUL.
+fn c_str(addr: usize) -> &'static [u8] {
+    let ptr = addr as *const u8;
+    let mut len = 0;
+    while unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
+
+/// Fill `buffer` from `offset` in the file `fd` of the library `name`.
+fn read_at(fd: usize, offset: usize, buffer: &mut [u8], name: &[u8]) {
+    if lseek(fd, offset, SEEK_SET).is_err() {
+        fail("cannot read", name);
+    }
+    let mut read_len = 0;
+    while read_len < buffer.len() {
+        match read(fd, &mut buffer[read_len..]) {
+            Ok(len) if len > 0 => read_len += len,
+            _ => fail("truncated file", name),
+        }
+    }
+}
+
+fn as_bytes_mut<T: ?Sized>(value: &mut T) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(value as *mut T as *mut u8, core::mem::size_of_val(value)) }
+}
+
+fn page_down(addr: usize) -> usize {
+    addr & !(PAGE_SIZE - 1)
+}
+
+fn page_up(addr: usize) -> usize {
+    page_down(addr + PAGE_SIZE - 1)
+}
@@ -0,0 +1,80 @@
+//! The few system calls of the loader, which runs before any library is loaded.
+
+const SYS_READ: usize = 1;
+const SYS_WRITE: usize = 2;
+const SYS_EXIT: usize = 3;
+const SYS_OPEN: usize = 10;
+const SYS_CLOSE: usize = 11;
+const SYS_LSEEK: usize = 12;
+const SYS_MMAP: usize = 35;
+const SYS_MPROTECT: usize = 37;
+
+pub const O_RDONLY: usize = 0;
+pub const SEEK_SET: usize = 0;
+
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// The return value of the kernel, a negated error number on failure.
+pub type SysResult = Result<usize, isize>;
+
+fn system_call(id: usize, args: [usize; 6]) -> SysResult {
+    let ret: isize;
+    unsafe {
+        asm!(
+            "syscall",
+            inlateout("rax") id => ret,
+            in("rdi") args[0],
+            in("rsi") args[1],
+            in("rdx") args[2],
+            in("r10") args[3],
+            in("r8") args[4],
+            in("r9") args[5],
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        );
+    }
+    if ret < 0 {
+        Err(ret)
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+pub fn read(fd: usize, buffer: &mut [u8]) -> SysResult {
+    system_call(SYS_READ, [fd, buffer.as_mut_ptr() as usize, buffer.len(), 0, 0, 0])
+}
+
+pub fn write(fd: usize, buffer: &[u8]) -> SysResult {
+    system_call(SYS_WRITE, [fd, buffer.as_ptr() as usize, buffer.len(), 0, 0, 0])
+}
+
+pub fn exit(exit_code: i32) -> ! {
+    let _ = system_call(SYS_EXIT, [exit_code as usize, 0, 0, 0, 0, 0]);
+    unreachable!("sys_exit never returns!");
+}
+
+/// Open `path`, which ends with a '\0'.
+pub fn open(path: &[u8], flags: usize) -> SysResult {
+    system_call(SYS_OPEN, [path.as_ptr() as usize, flags, 0, 0, 0, 0])
+}
+
+pub fn close(fd: usize) -> SysResult {
+    system_call(SYS_CLOSE, [fd, 0, 0, 0, 0, 0])
+}
+
+pub fn lseek(fd: usize, offset: usize, whence: usize) -> SysResult {
+    system_call(SYS_LSEEK, [fd, offset, whence, 0, 0, 0])
+}
+
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> SysResult {
+    system_call(SYS_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> SysResult {
+    system_call(SYS_MPROTECT, [addr, len, prot, 0, 0, 0])
+}
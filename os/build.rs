@@ -1,16 +1,87 @@
-use std::fs::{read_dir, File};
+use std::env;
+use std::fs::{read, read_dir, File};
 use std::io::{Result, Write};
+use std::path::{Path, PathBuf};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
+    println!("cargo:rerun-if-changed=../ld/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
-    insert_app_data().unwrap();
+    println!("cargo:rerun-if-changed={}", LOADER_PATH);
+    println!("cargo:rerun-if-changed={}", ROOTFS_PATH);
+    build_initramfs().unwrap();
 }
 
 static TARGET_PATH: &str = "../user/target/x86_64-os/release/";
+/// The dynamic loader, which the programs ask for as /lib/ld.so.
+static LOADER_PATH: &str = "../ld/target/x86_64-os/release/ld";
+/// Shared libraries the programs are linked against, installed in /lib.
+static LIBRARIES: &[&str] = &["libuser_lib.so"];
+/// Data files shipped in the initramfs as they are laid out here.
+static ROOTFS_PATH: &str = "../user/rootfs/";
+
+/// Write a cpio archive in the "newc" format, unpacked into the root file system at boot.
+struct CpioWriter {
+    file: File,
+    ino: u32,
+}
+
+impl CpioWriter {
+    fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            ino: 1,
+        })
+    }
+
+    fn entry(&mut self, name: &str, mode: u32, data: &[u8]) -> Result<()> {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            self.ino,
+            mode,
+            0, // uid
+            0, // gid
+            1, // nlink
+            0, // mtime
+            data.len(),
+            0, // devmajor
+            0, // devminor
+            0, // rdevmajor
+            0, // rdevminor
+            name.len() + 1,
+            0, // check
+        );
+        self.ino += 1;
+        self.file.write_all(header.as_bytes())?;
+        self.file.write_all(name.as_bytes())?;
+        self.file.write_all(&[0])?;
+        self.pad(header.len() + name.len() + 1)?;
+        self.file.write_all(data)?;
+        self.pad(data.len())
+    }
+
+    fn pad(&mut self, len: usize) -> Result<()> {
+        let padding = (4 - len % 4) % 4;
+        self.file.write_all(&[0; 4][..padding])
+    }
+
+    fn directory(&mut self, name: &str) -> Result<()> {
+        self.entry(name, 0o040755, &[])
+    }
+
+    fn file(&mut self, name: &str, mode: u32, data: &[u8]) -> Result<()> {
+        self.entry(name, 0o100000 | mode, data)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.entry("TRAILER!!!", 0, &[])
+    }
+}
+
+fn build_initramfs() -> Result<()> {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mut cpio = CpioWriter::new(&out_dir.join("initramfs.cpio"))?;
 
-fn insert_app_data() -> Result<()> {
-    let mut f = File::create("src/link_app.S").unwrap();
     let mut apps: Vec<_> = read_dir("../user/src/bin")
         .unwrap()
         .into_iter()
@@ -22,45 +93,39 @@ fn insert_app_data() -> Result<()> {
         .collect();
     apps.sort();
 
-    writeln!(
-        f,
-        r#"
-    .align 4
-    .section .data
-    .global _num_app
-_num_app:
-    .quad {}"#,
-        apps.len()
-    )?;
-
-    for i in 0..apps.len() {
-        writeln!(f, r#"    .quad app_{}_start"#, i)?;
-    }
-    writeln!(f, r#"    .quad app_{}_end"#, apps.len() - 1)?;
-    writeln!(
-        f,
-        r#"
-    .global _app_names
-_app_names:"#
-    )?;
+    cpio.directory("bin")?;
     for app in apps.iter() {
-        writeln!(f, r#"    .string "{}""#, app)?;
+        println!("bin/{}", app);
+        let data = read(format!("{}{}", TARGET_PATH, app))?;
+        cpio.file(&format!("bin/{}", app), 0o755, &data)?;
+    }
+    cpio.directory("lib")?;
+    println!("lib/ld.so");
+    cpio.file("lib/ld.so", 0o755, &read(LOADER_PATH)?)?;
+    for library in LIBRARIES.iter() {
+        println!("lib/{}", library);
+        let data = read(format!("{}{}", TARGET_PATH, library))?;
+        cpio.file(&format!("lib/{}", library), 0o755, &data)?;
     }
+    if Path::new(ROOTFS_PATH).is_dir() {
+        add_tree(&mut cpio, Path::new(ROOTFS_PATH), "")?;
+    }
+    cpio.finish()
+}
 
-    for (idx, app) in apps.iter().enumerate() {
-        println!("app_{}: {}", idx, app);
-        writeln!(
-            f,
-            r#"
-    .section .data
-    .global app_{0}_start
-    .global app_{0}_end
-    .align 4
-app_{0}_start:
-    .incbin "{2}{1}"
-app_{0}_end:"#,
-            idx, app, TARGET_PATH
-        )?;
+/// Add everything under `dir` to the archive, directories before their contents.
+fn add_tree(cpio: &mut CpioWriter, dir: &Path, prefix: &str) -> Result<()> {
+    let mut entries: Vec<_> = read_dir(dir)?.collect::<Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry.file_name().into_string().unwrap());
+        if entry.file_type()?.is_dir() {
+            cpio.directory(&name)?;
+            add_tree(cpio, &entry.path(), &format!("{}/", name))?;
+        } else {
+            println!("{}", name);
+            cpio.file(&name, 0o644, &read(entry.path())?)?;
+        }
     }
     Ok(())
 }
@@ -6,8 +6,9 @@ use x86_64::{
     VirtAddr,
 };
 
-pub const HEAP_START: usize = 0x5000000;
-pub const HEAP_SIZE: usize = 0x0100000; // 16MiB;
+/// In a level 4 entry of its own, see `memory::KERNEL_PML4_START`.
+pub const HEAP_START: usize = 0xffff_8080_0000_0000;
+pub const HEAP_SIZE: usize = 0x0400000; // 4MiB
 
 pub struct Stupid;
 
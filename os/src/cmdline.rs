@@ -0,0 +1,25 @@
+use crate::println;
+use alloc::string::String;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Where the kernel command line is read from, the bootloader does not pass one.
+const CMDLINE_PATH: &str = "/etc/cmdline";
+
+lazy_static! {
+    static ref CMDLINE: Mutex<String> = Mutex::new(String::new());
+}
+
+/// Read the command line from the root file system, empty if there is no such file.
+pub fn init() {
+    if let Ok(data) = crate::fs::read_file(CMDLINE_PATH) {
+        let cmdline = String::from_utf8_lossy(&data);
+        *CMDLINE.lock() = String::from(cmdline.trim());
+        println!("[kernel] Command line: {}", cmdline.trim());
+    }
+}
+
+/// Whether the boot option `name` was given.
+pub fn has_option(name: &str) -> bool {
+    CMDLINE.lock().split_whitespace().any(|option| option == name)
+}
@@ -0,0 +1,13 @@
+use crate::serial::SERIAL1;
+use crate::vga::WRITER;
+use x86_64::instructions::interrupts;
+
+/// Write raw bytes to the VGA text buffer and the serial port. Bytes VGA can not show
+/// are replaced there, while the serial port receives them unchanged.
+pub fn write_bytes(bytes: &[u8]) {
+    interrupts::without_interrupts(|| {
+        WRITER.lock().write_bytes(bytes);
+        let mut serial = SERIAL1.lock();
+        bytes.iter().for_each(|&byte| serial.send(byte));
+    });
+}
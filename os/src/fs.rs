@@ -0,0 +1,334 @@
+pub mod initramfs;
+pub mod inode_file;
+pub mod pipe;
+pub mod stdio;
+pub mod tmpfs;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FsError {
+    NotFound,
+    NotDirectory,
+    IsDirectory,
+    AlreadyExists,
+    NotEmpty,
+    InvalidArgument,
+    NotSupported,
+    BadDescriptor,
+    BrokenPipe,
+    FileTooLarge,
+    NoSpace,
+    /// A signal arrived while blocked.
+    Interrupted,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InodeKind {
+    File,
+    Directory,
+    CharDevice,
+    Fifo,
+}
+
+/// File status, laid out as `user_lib::Stat`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Stat {
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub size: u64,
+}
+
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+
+impl Stat {
+    pub fn new(ino: usize, kind: InodeKind, size: usize) -> Self {
+        let mode = match kind {
+            InodeKind::File => S_IFREG | 0o644,
+            InodeKind::Directory => S_IFDIR | 0o755,
+            InodeKind::CharDevice => S_IFCHR | 0o620,
+            InodeKind::Fifo => S_IFIFO | 0o600,
+        };
+        Self {
+            ino: ino as u64,
+            mode,
+            nlink: 1,
+            size: size as u64,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
+
+impl SeekFrom {
+    pub fn new(offset: isize, whence: usize) -> Result<Self, FsError> {
+        match whence {
+            0 if offset >= 0 => Ok(SeekFrom::Start(offset as usize)),
+            1 => Ok(SeekFrom::Current(offset)),
+            2 => Ok(SeekFrom::End(offset)),
+            _ => Err(FsError::InvalidArgument),
+        }
+    }
+}
+
+/// Flags of `sys_open`, with the values used by Linux.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OpenFlags(pub u32);
+
+impl OpenFlags {
+    pub const RDONLY: u32 = 0;
+    pub const WRONLY: u32 = 1 << 0;
+    pub const RDWR: u32 = 1 << 1;
+    pub const CREATE: u32 = 1 << 6;
+    pub const TRUNC: u32 = 1 << 9;
+    pub const APPEND: u32 = 1 << 10;
+
+    pub fn contains(self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+    pub fn readable(self) -> bool {
+        !self.contains(Self::WRONLY)
+    }
+    pub fn writable(self) -> bool {
+        self.contains(Self::WRONLY) || self.contains(Self::RDWR)
+    }
+}
+
+/// A node of a file system.
+pub trait Inode: Send + Sync {
+    fn kind(&self) -> InodeKind;
+    fn stat(&self) -> Stat;
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError>;
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError>;
+    fn truncate(&self, len: usize) -> Result<(), FsError>;
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError>;
+    fn create(&self, name: &str, kind: InodeKind) -> Result<Arc<dyn Inode>, FsError>;
+    /// Add an entry for an existing inode to this directory.
+    fn link(&self, name: &str, inode: Arc<dyn Inode>) -> Result<(), FsError>;
+    /// Remove an entry of any kind from this directory.
+    fn unlink(&self, name: &str) -> Result<Arc<dyn Inode>, FsError>;
+    fn entries(&self) -> Result<Vec<(String, Arc<dyn Inode>)>, FsError>;
+}
+
+/// Directory entry returned by `sys_getdents`, laid out as `user_lib::Dirent`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Dirent {
+    pub ino: u64,
+    pub kind: u8,
+    pub name: [u8; NAME_MAX + 1],
+}
+
+pub const NAME_MAX: usize = 255;
+
+impl Dirent {
+    pub const fn empty() -> Self {
+        Self {
+            ino: 0,
+            kind: 0,
+            name: [0; NAME_MAX + 1],
+        }
+    }
+    pub fn new(name: &str, inode: &Arc<dyn Inode>) -> Self {
+        let stat = inode.stat();
+        let mut dirent = Self {
+            ino: stat.ino,
+            kind: (stat.mode >> 12) as u8,
+            name: [0; NAME_MAX + 1],
+        };
+        let len = name.len().min(NAME_MAX);
+        dirent.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        dirent
+    }
+}
+
+/// An open file, shared by every descriptor `dup` or `fork` made from the same `open`.
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError>;
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError>;
+    fn seek(&self, _pos: SeekFrom) -> Result<usize, FsError> {
+        Err(FsError::NotSupported)
+    }
+    fn stat(&self) -> Result<Stat, FsError>;
+    /// Fill `dirents` with the next entries of a directory, 0 at its end.
+    fn getdents(&self, _dirents: &mut [Dirent]) -> Result<usize, FsError> {
+        Err(FsError::NotDirectory)
+    }
+    /// Inode the file was opened from, for `mmap`.
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        None
+    }
+}
+
+lazy_static! {
+    static ref ROOT_INODE: Mutex<Option<Arc<dyn Inode>>> = Mutex::new(None);
+}
+
+pub fn mount_root(inode: Arc<dyn Inode>) {
+    *ROOT_INODE.lock() = Some(inode);
+}
+
+pub fn root_inode() -> Result<Arc<dyn Inode>, FsError> {
+    ROOT_INODE.lock().clone().ok_or(FsError::NotFound)
+}
+
+/// Mount a tmpfs as the root and fill it with the initramfs.
+pub fn init() {
+    mount_root(Arc::new(tmpfs::TmpInode::new(InodeKind::Directory)));
+    let files = initramfs::unpack().expect("Unpack initramfs failed.");
+    crate::println!("[kernel] Unpacked {} files from initramfs.", files);
+}
+
+/// Resolve `path` against the working directory `cwd` into an absolute path
+/// without `.` or `..` components.
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    let base = if path.starts_with('/') { "" } else { cwd };
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    let mut absolute = String::new();
+    for component in components.iter() {
+        absolute.push('/');
+        absolute.push_str(component);
+    }
+    if absolute.is_empty() {
+        absolute.push('/');
+    }
+    absolute
+}
+
+/// Split an absolute path into its parent directory and the last component.
+fn split_path(path: &str) -> Result<(Arc<dyn Inode>, &str), FsError> {
+    let index = path.rfind('/').ok_or(FsError::InvalidArgument)?;
+    let name = &path[index + 1..];
+    if name.is_empty() {
+        return Err(FsError::InvalidArgument);
+    }
+    let parent = lookup_path(&path[..index])?;
+    if parent.kind() != InodeKind::Directory {
+        return Err(FsError::NotDirectory);
+    }
+    Ok((parent, name))
+}
+
+/// Look up an absolute path as given by `absolute_path`.
+pub fn lookup_path(path: &str) -> Result<Arc<dyn Inode>, FsError> {
+    path.split('/')
+        .filter(|c| !c.is_empty())
+        .try_fold(root_inode()?, |inode, name| inode.lookup(name))
+}
+
+pub fn open_file(path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+    use inode_file::InodeFile;
+    let inode = match lookup_path(path) {
+        Ok(inode) => inode,
+        Err(FsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+            let (parent, name) = split_path(path)?;
+            parent.create(name, InodeKind::File)?
+        }
+        Err(err) => return Err(err),
+    };
+    if inode.kind() == InodeKind::Directory && flags.writable() {
+        return Err(FsError::IsDirectory);
+    }
+    if flags.contains(OpenFlags::TRUNC) && flags.writable() {
+        inode.truncate(0)?;
+    }
+    Ok(Arc::new(InodeFile::new(inode, flags)))
+}
+
+/// Read a whole regular file.
+pub fn read_file(path: &str) -> Result<Vec<u8>, FsError> {
+    let inode = lookup_path(path)?;
+    if inode.kind() != InodeKind::File {
+        return Err(FsError::IsDirectory);
+    }
+    let mut data = vec![0; inode.stat().size as usize];
+    let len = inode.read_at(0, &mut data)?;
+    data.truncate(len);
+    Ok(data)
+}
+
+pub fn make_directory(path: &str) -> Result<(), FsError> {
+    let (parent, name) = split_path(path)?;
+    parent.create(name, InodeKind::Directory).map(|_| ())
+}
+
+fn is_empty_directory(inode: &Arc<dyn Inode>) -> Result<bool, FsError> {
+    match inode.kind() {
+        InodeKind::Directory => Ok(inode.entries()?.is_empty()),
+        _ => Err(FsError::NotDirectory),
+    }
+}
+
+pub fn unlink(path: &str) -> Result<(), FsError> {
+    let (parent, name) = split_path(path)?;
+    if parent.lookup(name)?.kind() == InodeKind::Directory {
+        return Err(FsError::IsDirectory);
+    }
+    parent.unlink(name).map(|_| ())
+}
+
+pub fn remove_directory(path: &str) -> Result<(), FsError> {
+    let (parent, name) = split_path(path)?;
+    if !is_empty_directory(&parent.lookup(name)?)? {
+        return Err(FsError::NotEmpty);
+    }
+    parent.unlink(name).map(|_| ())
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), FsError> {
+    if old_path == new_path {
+        return Ok(());
+    }
+    // A directory can not be moved into itself.
+    if new_path.starts_with(old_path) && new_path.as_bytes()[old_path.len()] == b'/' {
+        return Err(FsError::InvalidArgument);
+    }
+    let (old_parent, old_name) = split_path(old_path)?;
+    let (new_parent, new_name) = split_path(new_path)?;
+    let inode = old_parent.lookup(old_name)?;
+    match new_parent.lookup(new_name) {
+        Ok(target) => {
+            match (inode.kind(), target.kind()) {
+                (InodeKind::Directory, InodeKind::Directory) => {
+                    if !is_empty_directory(&target)? {
+                        return Err(FsError::NotEmpty);
+                    }
+                }
+                (InodeKind::Directory, _) => return Err(FsError::NotDirectory),
+                (_, InodeKind::Directory) => return Err(FsError::IsDirectory),
+                _ => {}
+            }
+            new_parent.unlink(new_name)?;
+        }
+        Err(FsError::NotFound) => {}
+        Err(err) => return Err(err),
+    }
+    new_parent.link(new_name, inode)?;
+    old_parent.unlink(old_name).map(|_| ())
+}
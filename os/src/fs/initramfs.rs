@@ -0,0 +1,79 @@
+use super::{absolute_path, lookup_path, make_directory, open_file, FsError, OpenFlags};
+use crate::println;
+use core::str;
+
+/// cpio archive in the "newc" format generated by `build.rs`.
+static INITRAMFS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+const HEADER_SIZE: usize = 110;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Field `index` of a newc header, an 8 digit hexadecimal number after the magic.
+fn header_field(header: &[u8], index: usize) -> Result<usize, FsError> {
+    let start = 6 + index * 8;
+    let digits = str::from_utf8(&header[start..start + 8]).map_err(|_| FsError::InvalidArgument)?;
+    usize::from_str_radix(digits, 16).map_err(|_| FsError::InvalidArgument)
+}
+
+/// Unpack the initramfs into the root file system, returning the number of files.
+pub fn unpack() -> Result<usize, FsError> {
+    let mut offset = 0;
+    let mut files = 0;
+    loop {
+        let header = INITRAMFS
+            .get(offset..offset + HEADER_SIZE)
+            .ok_or(FsError::InvalidArgument)?;
+        if &header[..6] != b"070701" {
+            return Err(FsError::InvalidArgument);
+        }
+        let mode = header_field(header, 1)? as u32;
+        let file_size = header_field(header, 6)?;
+        let name_size = header_field(header, 11)?;
+        let name_start = offset + HEADER_SIZE;
+        // The name size includes the trailing '\0'.
+        let name_len = name_size.checked_sub(1).ok_or(FsError::InvalidArgument)?;
+        let name = INITRAMFS
+            .get(name_start..name_start + name_len)
+            .and_then(|name| str::from_utf8(name).ok())
+            .ok_or(FsError::InvalidArgument)?;
+        let data_start = align4(name_start + name_size);
+        let data = INITRAMFS
+            .get(data_start..data_start + file_size)
+            .ok_or(FsError::InvalidArgument)?;
+        offset = align4(data_start + file_size);
+        if name == "TRAILER!!!" {
+            return Ok(files);
+        }
+
+        let path = absolute_path("/", name);
+        match mode & S_IFMT {
+            S_IFDIR => match make_directory(&path) {
+                Ok(()) | Err(FsError::AlreadyExists) => {}
+                Err(err) => return Err(err),
+            },
+            S_IFREG => {
+                let flags = OpenFlags(OpenFlags::WRONLY | OpenFlags::CREATE | OpenFlags::TRUNC);
+                open_file(&path, flags)?.write(data)?;
+                files += 1;
+            }
+            _ => println!("[kernel] Skipped initramfs entry {} of mode {:o}.", name, mode),
+        }
+    }
+}
+
+/// Print the programs unpacked into `/bin`.
+pub fn list_apps() {
+    println!("/**** APPS ****");
+    if let Ok(entries) = lookup_path("/bin").and_then(|bin| bin.entries()) {
+        for (name, _) in entries.iter() {
+            println!("{}", name);
+        }
+    }
+    println!("**************/")
+}
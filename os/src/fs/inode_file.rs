@@ -0,0 +1,76 @@
+use super::{Dirent, File, FsError, Inode, OpenFlags, SeekFrom, Stat};
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/// A file opened from an inode, with its own offset.
+pub struct InodeFile {
+    inode: Arc<dyn Inode>,
+    flags: OpenFlags,
+    offset: Mutex<usize>,
+}
+
+impl InodeFile {
+    pub fn new(inode: Arc<dyn Inode>, flags: OpenFlags) -> Self {
+        Self {
+            inode,
+            flags,
+            offset: Mutex::new(0),
+        }
+    }
+}
+
+impl File for InodeFile {
+    fn readable(&self) -> bool {
+        self.flags.readable()
+    }
+    fn writable(&self) -> bool {
+        self.flags.writable()
+    }
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        let len = self.inode.read_at(*offset, buf)?;
+        *offset += len;
+        Ok(len)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *offset = self.inode.stat().size as usize;
+        }
+        let len = self.inode.write_at(*offset, buf)?;
+        *offset += len;
+        Ok(len)
+    }
+    fn seek(&self, pos: SeekFrom) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        let new_offset = match pos {
+            SeekFrom::Start(start) => Some(start as isize),
+            SeekFrom::Current(delta) => (*offset as isize).checked_add(delta),
+            SeekFrom::End(delta) => (self.inode.stat().size as isize).checked_add(delta),
+        };
+        match new_offset {
+            Some(new_offset) if new_offset >= 0 => *offset = new_offset as usize,
+            _ => return Err(FsError::InvalidArgument),
+        }
+        Ok(*offset)
+    }
+    fn stat(&self) -> Result<Stat, FsError> {
+        Ok(self.inode.stat())
+    }
+    /// The offset of a directory counts the entries already returned.
+    fn getdents(&self, dirents: &mut [Dirent]) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        let entries = self.inode.entries()?;
+        let count = entries
+            .iter()
+            .skip(*offset)
+            .zip(dirents.iter_mut())
+            .map(|((name, inode), dirent)| *dirent = Dirent::new(name, inode))
+            .count();
+        *offset += count;
+        Ok(count)
+    }
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        Some(self.inode.clone())
+    }
+}
@@ -0,0 +1,147 @@
+use super::{File, FsError, InodeKind, Stat};
+use crate::process::block_current_and_run_next;
+use crate::process::signal::signal_pending;
+use crate::process::wait_queue::WaitQueue;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+const PIPE_BUFFER_SIZE: usize = 4096;
+
+/// Ring buffer shared by both ends of a pipe.
+struct PipeBuffer {
+    data: [u8; PIPE_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    readers: usize,
+    writers: usize,
+}
+
+impl PipeBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len);
+        for byte in buf[..len].iter_mut() {
+            *byte = self.data[self.head];
+            self.head = (self.head + 1) % PIPE_BUFFER_SIZE;
+        }
+        self.len -= len;
+        len
+    }
+    fn write(&mut self, buf: &[u8]) -> usize {
+        let len = buf.len().min(PIPE_BUFFER_SIZE - self.len);
+        for &byte in buf[..len].iter() {
+            self.data[(self.head + self.len) % PIPE_BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+        len
+    }
+}
+
+struct PipeInner {
+    buffer: Mutex<PipeBuffer>,
+    read_wait_queue: Mutex<WaitQueue>,
+    write_wait_queue: Mutex<WaitQueue>,
+}
+
+/// One end of a pipe. Every descriptor of an end shares it, so it is dropped
+/// when the last descriptor of that end is closed.
+pub struct Pipe {
+    readable: bool,
+    inner: Arc<PipeInner>,
+}
+
+/// Create a pipe, returning its read end and its write end.
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let inner = Arc::new(PipeInner {
+        buffer: Mutex::new(PipeBuffer {
+            data: [0; PIPE_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+        }),
+        read_wait_queue: Mutex::new(WaitQueue::new()),
+        write_wait_queue: Mutex::new(WaitQueue::new()),
+    });
+    let read_end = Arc::new(Pipe {
+        readable: true,
+        inner: inner.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        readable: false,
+        inner,
+    });
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        !self.readable
+    }
+    /// Block until some data is available, 0 once every writer is closed.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut buffer = self.inner.buffer.lock();
+            let len = buffer.read(buf);
+            if len > 0 {
+                self.inner.write_wait_queue.lock().wake_all();
+                return Ok(len);
+            }
+            if buffer.writers == 0 {
+                return Ok(0);
+            }
+            drop(buffer);
+            if signal_pending() {
+                return Err(FsError::Interrupted);
+            }
+            // Pipes are only used from process context, which the kernel never preempts.
+            block_current_and_run_next(&self.inner.read_wait_queue);
+        }
+    }
+    /// Block until all of `buf` is written. Once every reader is closed, the bytes already
+    /// written are reported, and a failure only if there are none. A signal ends the write early.
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        let mut written = 0;
+        loop {
+            let mut buffer = self.inner.buffer.lock();
+            if buffer.readers == 0 {
+                return if written > 0 { Ok(written) } else { Err(FsError::BrokenPipe) };
+            }
+            let len = buffer.write(&buf[written..]);
+            if len > 0 {
+                self.inner.read_wait_queue.lock().wake_all();
+            }
+            written += len;
+            if written == buf.len() {
+                return Ok(written);
+            }
+            drop(buffer);
+            if signal_pending() {
+                return if written > 0 { Ok(written) } else { Err(FsError::Interrupted) };
+            }
+            block_current_and_run_next(&self.inner.write_wait_queue);
+        }
+    }
+    fn stat(&self) -> Result<Stat, FsError> {
+        let len = self.inner.buffer.lock().len;
+        Ok(Stat::new(0, InodeKind::Fifo, len))
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut buffer = self.inner.buffer.lock();
+        if self.readable {
+            buffer.readers -= 1;
+            self.inner.write_wait_queue.lock().wake_all();
+        } else {
+            buffer.writers -= 1;
+            self.inner.read_wait_queue.lock().wake_all();
+        }
+    }
+}
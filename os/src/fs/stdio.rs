@@ -0,0 +1,70 @@
+use super::{File, FsError, InodeKind, Stat};
+
+/// Keyboard input.
+pub struct Stdin;
+
+/// Console output, see `console::write_bytes`.
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        use crate::interrupts::{STDIN_BUFFER, STDIN_WAIT_QUEUE};
+        use crate::process::block_current_and_run_next;
+        use crate::process::signal::signal_pending;
+        use x86_64::instructions::interrupts;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            // The keyboard handler must not fill the buffer between checking it and blocking.
+            interrupts::disable();
+            let mut stdin = STDIN_BUFFER.lock();
+            if !stdin.is_empty() {
+                let len = buf.len().min(stdin.len());
+                buf.iter_mut()
+                    .zip(stdin.drain(..len))
+                    .for_each(|(byte, c)| *byte = c);
+                drop(stdin);
+                interrupts::enable();
+                return Ok(len);
+            }
+            drop(stdin);
+            if signal_pending() {
+                interrupts::enable();
+                return Err(FsError::Interrupted);
+            }
+            block_current_and_run_next(&STDIN_WAIT_QUEUE);
+        }
+    }
+    fn write(&self, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::BadDescriptor)
+    }
+    fn stat(&self) -> Result<Stat, FsError> {
+        Ok(Stat::new(0, InodeKind::CharDevice, 0))
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::BadDescriptor)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        crate::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+    fn stat(&self) -> Result<Stat, FsError> {
+        Ok(Stat::new(0, InodeKind::CharDevice, 0))
+    }
+}
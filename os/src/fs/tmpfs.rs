@@ -0,0 +1,130 @@
+use super::{FsError, Inode, InodeKind, Stat};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+static NEXT_INO: AtomicUsize = AtomicUsize::new(1);
+
+enum Content {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, Arc<dyn Inode>>),
+}
+
+/// A file or directory of the RAM-backed tmpfs.
+pub struct TmpInode {
+    ino: usize,
+    content: Mutex<Content>,
+}
+
+impl TmpInode {
+    pub fn new(kind: InodeKind) -> Self {
+        let content = match kind {
+            InodeKind::Directory => Content::Directory(BTreeMap::new()),
+            _ => Content::File(Vec::new()),
+        };
+        Self {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            content: Mutex::new(content),
+        }
+    }
+}
+
+/// Grow `data` zero-filled or shrink it to `len` bytes, failing rather than aborting when
+/// the kernel heap has no room left.
+fn resize(data: &mut Vec<u8>, len: usize) -> Result<(), FsError> {
+    if len > data.len() {
+        data.try_reserve(len - data.len()).map_err(|_| FsError::NoSpace)?;
+    }
+    data.resize(len, 0);
+    Ok(())
+}
+
+impl Inode for TmpInode {
+    fn kind(&self) -> InodeKind {
+        match *self.content.lock() {
+            Content::File(_) => InodeKind::File,
+            Content::Directory(_) => InodeKind::Directory,
+        }
+    }
+    fn stat(&self) -> Stat {
+        match &*self.content.lock() {
+            Content::File(data) => Stat::new(self.ino, InodeKind::File, data.len()),
+            Content::Directory(entries) => {
+                Stat::new(self.ino, InodeKind::Directory, entries.len())
+            }
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        match &*self.content.lock() {
+            Content::File(data) => {
+                if offset >= data.len() {
+                    return Ok(0);
+                }
+                let len = buf.len().min(data.len() - offset);
+                buf[..len].copy_from_slice(&data[offset..offset + len]);
+                Ok(len)
+            }
+            Content::Directory(_) => Err(FsError::IsDirectory),
+        }
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        match &mut *self.content.lock() {
+            Content::File(data) => {
+                let end = offset.checked_add(buf.len()).ok_or(FsError::FileTooLarge)?;
+                if end > data.len() {
+                    resize(data, end)?;
+                }
+                data[offset..end].copy_from_slice(buf);
+                Ok(buf.len())
+            }
+            Content::Directory(_) => Err(FsError::IsDirectory),
+        }
+    }
+    fn truncate(&self, len: usize) -> Result<(), FsError> {
+        match &mut *self.content.lock() {
+            Content::File(data) => resize(data, len),
+            Content::Directory(_) => Err(FsError::IsDirectory),
+        }
+    }
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        match &*self.content.lock() {
+            Content::Directory(entries) => entries.get(name).cloned().ok_or(FsError::NotFound),
+            Content::File(_) => Err(FsError::NotDirectory),
+        }
+    }
+    fn create(&self, name: &str, kind: InodeKind) -> Result<Arc<dyn Inode>, FsError> {
+        let inode: Arc<dyn Inode> = Arc::new(TmpInode::new(kind));
+        self.link(name, inode.clone())?;
+        Ok(inode)
+    }
+    fn link(&self, name: &str, inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        match &mut *self.content.lock() {
+            Content::Directory(entries) => {
+                if entries.contains_key(name) {
+                    return Err(FsError::AlreadyExists);
+                }
+                entries.insert(name.to_string(), inode);
+                Ok(())
+            }
+            Content::File(_) => Err(FsError::NotDirectory),
+        }
+    }
+    fn unlink(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        match &mut *self.content.lock() {
+            Content::Directory(entries) => entries.remove(name).ok_or(FsError::NotFound),
+            Content::File(_) => Err(FsError::NotDirectory),
+        }
+    }
+    fn entries(&self) -> Result<Vec<(String, Arc<dyn Inode>)>, FsError> {
+        match &*self.content.lock() {
+            Content::Directory(entries) => Ok(entries
+                .iter()
+                .map(|(name, inode)| (name.clone(), inode.clone()))
+                .collect()),
+            Content::File(_) => Err(FsError::NotDirectory),
+        }
+    }
+}
@@ -99,3 +99,11 @@ pub fn init() {
         load_tss(task_state_seg);
     }
 }
+
+/// Stack the CPU switches to when an interrupt arrives in user mode.
+pub fn set_privilege_stack(top: usize) {
+    // The CPU reads the TSS from memory on every privilege change, so updating it in place
+    // is enough. Only the scheduler calls this, with interrupts not touching the TSS.
+    let tss = &*TSS as *const TaskStateSegment as *mut TaskStateSegment;
+    unsafe { (*tss).privilege_stack_table[0] = VirtAddr::new(top as u64) };
+}
@@ -1,15 +1,19 @@
 pub mod exception_handlers;
 use crate::gdt::ISTIndex;
-use crate::print;
+use crate::system_call::timer_start;
 use exception_handlers::*;
 use lazy_static::lazy_static;
 use pic8259_simple::ChainedPics;
 use spin::Mutex;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
 
 const PIC_1_OFFSET: u8 = 32;
 const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+/// Timer interrupts per second.
+pub const TIMER_FREQUENCY: u32 = 100;
+const PIT_FREQUENCY: u32 = 1193182;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum Interrupt {
@@ -64,6 +68,7 @@ lazy_static! {
             .set_handler_fn(stack_segment_fault_handler);
         idt.general_protection_fault
             .set_handler_fn(general_protection_fault_handler);
+        // Faults may block or schedule away, so they stay on the kernel stack of the task.
         idt.page_fault.set_handler_fn(page_fault_handler);
         idt.x87_floating_point
             .set_handler_fn(x87_floating_point_handler);
@@ -73,21 +78,22 @@ lazy_static! {
             .set_handler_fn(simd_floating_point_handler);
         idt.virtualization.set_handler_fn(virtualization_handler);
         idt.security_exception.set_handler_fn(security_handler);
-        idt[Interrupt::Timer.as_usize()].set_handler_fn(timer_handler);
+        // timer_start saves a full TrapFrame so that user code can be preempted.
+        idt[Interrupt::Timer.as_usize()].set_handler_fn(unsafe {
+            core::mem::transmute::<unsafe extern "C" fn(), HandlerFunc>(timer_start)
+        });
         idt[Interrupt::Keyboard.as_usize()].set_handler_fn(keyboard_handler);
         idt
     };
 }
 
-extern "x86-interrupt" fn timer_handler(_stack_frame: &mut InterruptStackFrame) {
-    // print!(".");
-    Interrupt::Timer.end_of_interrupt();
-}
-
-use alloc::vec::Vec;
+use crate::process::wait_queue::WaitQueue;
+use alloc::collections::VecDeque;
 use pc_keyboard::KeyCode;
 lazy_static! {
-    pub static ref STDIN_BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    pub static ref STDIN_BUFFER: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+    /// Readers waiting for `STDIN_BUFFER` to become non-empty.
+    pub static ref STDIN_WAIT_QUEUE: Mutex<WaitQueue> = Mutex::new(WaitQueue::new());
 }
 extern "x86-interrupt" fn keyboard_handler(_stack_frame: &mut InterruptStackFrame) {
     use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
@@ -95,7 +101,7 @@ extern "x86-interrupt" fn keyboard_handler(_stack_frame: &mut InterruptStackFram
 
     lazy_static! {
         static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore) //Ignore control now.
+            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::MapLettersToUnicode)
         );
     };
 
@@ -106,14 +112,25 @@ extern "x86-interrupt" fn keyboard_handler(_stack_frame: &mut InterruptStackFram
     if let Ok(Some(key_event)) = keyboard.add_byte(code) {
         if let Some(key) = keyboard.process_keyevent(key_event) {
             match key {
-                DecodedKey::Unicode(ch) => lock.push(ch as u8),
+                // Ctrl-C
+                DecodedKey::Unicode('\u{3}') => {
+                    crate::process::signal::keyboard_interrupt();
+                    crate::print!("^C");
+                }
+                DecodedKey::Unicode(ch) => {
+                    let mut bytes = [0; 4];
+                    lock.extend(ch.encode_utf8(&mut bytes).bytes());
+                }
                 DecodedKey::RawKey(key) => match key {
-                    KeyCode::Enter => lock.push('\n' as u8),
+                    KeyCode::Enter => lock.push_back('\n' as u8),
                     _ => {}
                 },
             }
         }
     }
+    if !lock.is_empty() {
+        STDIN_WAIT_QUEUE.lock().wake_all();
+    }
     Interrupt::Keyboard.end_of_interrupt();
 }
 
@@ -127,6 +144,18 @@ pub fn init_pic() {
     }
 }
 
+pub fn init_pit() {
+    use x86_64::instructions::port::Port;
+    let divisor = PIT_FREQUENCY / TIMER_FREQUENCY;
+    let mut command: Port<u8> = Port::new(0x43);
+    let mut channel0: Port<u8> = Port::new(0x40);
+    unsafe {
+        command.write(0x36); // Channel 0, lobyte/hibyte, square wave generator
+        channel0.write(divisor as u8);
+        channel0.write((divisor >> 8) as u8);
+    }
+}
+
 pub fn enable() {
     x86_64::instructions::interrupts::enable();
 }
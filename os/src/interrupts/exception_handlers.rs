@@ -1,10 +1,55 @@
+use crate::println;
 use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
+
+/// Signal a fault raised in user mode is reported as, numbered as on Linux.
+#[derive(Debug, Clone, Copy)]
+#[repr(isize)]
+pub enum FaultSignal {
+    SIGILL = 4,
+    SIGTRAP = 5,
+    SIGBUS = 7,
+    SIGFPE = 8,
+    SIGSEGV = 11,
+}
+
+impl FaultSignal {
+    /// Exit code the parent receives for a process killed by this signal.
+    pub fn exit_code(self) -> isize {
+        -(128 + self as isize)
+    }
+}
+
+fn from_user_mode(stack_frame: &InterruptStackFrame) -> bool {
+    stack_frame.code_segment & 0b11 == 3
+}
+
+fn kill_current_process(text: &str, signal: FaultSignal, stack_frame: &InterruptStackFrame) -> ! {
+    use crate::process::{current_process, exit_current_and_run_next};
+    let pid = current_process().map(|p| p.getpid());
+    println!(
+        "[kernel] {} in process {:?} at {:?}, killed by {:?}.",
+        text, pid, stack_frame.instruction_pointer, signal
+    );
+    // Handlers run with interrupts disabled, while the kernel keeps them enabled elsewhere.
+    x86_64::instructions::interrupts::enable();
+    exit_current_and_run_next(signal.exit_code());
+    unreachable!("A killed process should never be scheduled again.");
+}
+
 macro_rules! def_handler_func {
     ($name: tt, $text: expr) => {
         pub extern "x86-interrupt" fn $name(stack_frame: &mut InterruptStackFrame) {
             panic!("EXCEPTION: {}\n{:#?}", $text, stack_frame);
         }
     };
+    ($name: tt, $text: expr, $signal: expr) => {
+        pub extern "x86-interrupt" fn $name(stack_frame: &mut InterruptStackFrame) {
+            if from_user_mode(stack_frame) {
+                kill_current_process($text, $signal, stack_frame);
+            }
+            panic!("EXCEPTION: {}\n{:#?}", $text, stack_frame);
+        }
+    };
 }
 macro_rules! def_handler_func_with_errorcode {
     ($name: tt, $text: expr) => {
@@ -18,25 +63,75 @@ macro_rules! def_handler_func_with_errorcode {
             );
         }
     };
+    ($name: tt, $text: expr, $signal: expr) => {
+        pub extern "x86-interrupt" fn $name(
+            stack_frame: &mut InterruptStackFrame,
+            error_code: u64,
+        ) {
+            if from_user_mode(stack_frame) {
+                kill_current_process($text, $signal, stack_frame);
+            }
+            panic!(
+                "EXCEPTION: {}\n{:#?}\nErrorCode: {:x}",
+                $text, stack_frame, error_code
+            );
+        }
+    };
 }
 
-def_handler_func!(divide_error_handler, "Divide Error");
-def_handler_func!(debug_handler, "Debug");
-def_handler_func!(overflow_handler, "Overflow");
-def_handler_func!(device_not_available_handler, "Device Not Available");
+def_handler_func!(divide_error_handler, "Divide Error", FaultSignal::SIGFPE);
+def_handler_func!(debug_handler, "Debug", FaultSignal::SIGTRAP);
+def_handler_func!(overflow_handler, "Overflow", FaultSignal::SIGSEGV);
+def_handler_func!(
+    device_not_available_handler,
+    "Device Not Available",
+    FaultSignal::SIGFPE
+);
 def_handler_func!(non_maskable_interrupt_handler, "Non-maskable Interrupt");
-def_handler_func!(bound_range_exceeded_handler, "Bound Range Exceeded");
-def_handler_func!(invalid_opcode_handler, "Invalid Opcode");
-def_handler_func!(x87_floating_point_handler, "x87 Floating Point");
-def_handler_func!(simd_floating_point_handler, "SIMD Floating Point");
+def_handler_func!(
+    bound_range_exceeded_handler,
+    "Bound Range Exceeded",
+    FaultSignal::SIGSEGV
+);
+def_handler_func!(
+    invalid_opcode_handler,
+    "Invalid Opcode",
+    FaultSignal::SIGILL
+);
+def_handler_func!(
+    x87_floating_point_handler,
+    "x87 Floating Point",
+    FaultSignal::SIGFPE
+);
+def_handler_func!(
+    simd_floating_point_handler,
+    "SIMD Floating Point",
+    FaultSignal::SIGFPE
+);
 def_handler_func!(virtualization_handler, "Virtualization");
 
-def_handler_func_with_errorcode!(invalid_tss_handler, "Invalid TSS");
-def_handler_func_with_errorcode!(alignment_check_handler, "Alignment Check");
-def_handler_func_with_errorcode!(segment_not_present_handler, "Segment not Present");
-def_handler_func_with_errorcode!(stack_segment_fault_handler, "Stack Segment Fault");
+def_handler_func_with_errorcode!(invalid_tss_handler, "Invalid TSS", FaultSignal::SIGSEGV);
+def_handler_func_with_errorcode!(
+    alignment_check_handler,
+    "Alignment Check",
+    FaultSignal::SIGBUS
+);
+def_handler_func_with_errorcode!(
+    segment_not_present_handler,
+    "Segment not Present",
+    FaultSignal::SIGBUS
+);
+def_handler_func_with_errorcode!(
+    stack_segment_fault_handler,
+    "Stack Segment Fault",
+    FaultSignal::SIGBUS
+);
 def_handler_func_with_errorcode!(security_handler, "Security");
-def_handler_func_with_errorcode!(general_protection_fault_handler, "General Protection Fault");
+def_handler_func_with_errorcode!(
+    general_protection_fault_handler,
+    "General Protection Fault",
+    FaultSignal::SIGSEGV
+);
 
 pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut InterruptStackFrame) {
     crate::println!("EXCEPTION: Breakpoint\n{:#?}", stack_frame);
@@ -57,5 +152,25 @@ pub extern "x86-interrupt" fn page_fault_handler(
     stack_frame: &mut InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
-    panic!("EXCEPTION: Page Fault\n{:#?}\nErrorCode: {:#?}", stack_frame, error_code);
+    use crate::process::current_process;
+    use x86_64::registers::control::Cr2;
+    let addr = Cr2::read();
+    if from_user_mode(stack_frame) {
+        // The kernel only reaches user memory through `translate_user`.
+        let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+        if let Some(process) = current_process() {
+            if process.inner_lock().memory_set.handle_page_fault(addr, write) {
+                return;
+            }
+        }
+        println!(
+            "[kernel] Page fault at {:?}, ErrorCode: {:?}",
+            addr, error_code
+        );
+        kill_current_process("Page Fault", FaultSignal::SIGSEGV, stack_frame);
+    }
+    panic!(
+        "EXCEPTION: Page Fault\n{:#?}\nAddress: {:?}\nErrorCode: {:#?}",
+        stack_frame, addr, error_code
+    );
 }
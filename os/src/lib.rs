@@ -7,16 +7,20 @@
 #![feature(naked_functions)]
 #![feature(global_asm)]
 #![feature(alloc_error_handler)]
+#![feature(try_reserve)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 pub mod allocator;
+pub mod cmdline;
+pub mod console;
+pub mod fs;
 pub mod gdt;
 pub mod interrupts;
 pub mod memory;
+pub mod random;
 pub mod serial;
 pub mod system_call;
 pub mod vga;
-pub mod loader;
 pub mod process;
 
 extern crate alloc;
@@ -27,6 +31,7 @@ pub fn init() {
     gdt::init();
     interrupts::init_idt();
     interrupts::init_pic();
+    interrupts::init_pit();
     interrupts::enable();
 }
 
@@ -70,9 +75,6 @@ pub fn test_panic_handler(info: &PanicInfo) -> ! {
 #[cfg(test)]
 use bootloader::{entry_point, BootInfo};
 
-#[cfg(test)]
-global_asm!(include_str!("link_app.S"));
-
 #[cfg(test)]
 entry_point!(test_kernel_main);
 
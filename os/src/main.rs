@@ -3,17 +3,14 @@
 #![feature(custom_test_frameworks)]
 #![test_runner(os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
-#![feature(global_asm)]
 
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
-use os::{allocator::heap_init, memory, process, system_call};
+use os::{allocator::heap_init, cmdline, fs, memory, process, system_call};
 extern crate alloc;
 #[allow(unused_imports)]
 use os::println;
 use x86_64::VirtAddr;
-use os::loader::list_apps;
-global_asm!(include_str!("link_app.S"));
 
 entry_point!(kernel_main);
 
@@ -24,16 +21,19 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let mut mapper = unsafe { os::memory::init(phys_mem_offset) };
     memory::init_frame_allocator(&boot_info.memory_map);
     println!("[kernel] Frame allocator initialized.");
+    memory::init_kernel_space();
     heap_init(&mut mapper).expect("Initialize heap failed.");
     println!("[kernel] Heap initialized.");
-    memory::init_kernel_stack(&mut mapper);
-    println!("[kernel] Kernel stack initialized.");
-    list_apps();
+    fs::init();
+    println!("[kernel] Root file system mounted.");
+    cmdline::init();
+    fs::initramfs::list_apps();
     system_call::trap_init();
 
     println!("----------");
     println!("[user programs]");
     process::add_initproc();
+    process::signal::init();
     process::run_processes();
 
     #[cfg(test)]
@@ -1,22 +1,22 @@
 use alloc::boxed::Box;
-use lazy_static::lazy_static;
 use spin::Mutex;
 use x86_64::{
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame,
+        Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
 
-use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
-use x86_64::structures::paging::{PageTableFlags, Translate};
+use bootloader::bootinfo::MemoryMap;
+use x86_64::structures::paging::PageTableFlags;
 
+pub mod elf;
+pub mod frame_allocator;
 pub mod memory_set;
+pub mod user_access;
 
-pub struct MemoryFrameAllocator {
-    memory_map: Option<&'static MemoryMap>,
-    next: usize,
-}
+use frame_allocator::MemoryFrameAllocator;
 
 pub struct EmptyFrameAllocator;
 
@@ -26,48 +26,15 @@ unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
     }
 }
 
-impl MemoryFrameAllocator {
-    pub fn new() -> Self {
-        MemoryFrameAllocator {
-            memory_map: None,
-            next: 0,
-        }
-    }
-    pub fn get_mut(&mut self) -> &mut Self {
-        self
-    }
-    pub unsafe fn init(&mut self, memory_map: &'static MemoryMap) {
-        self.memory_map = Some(memory_map);
-    }
-
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        self.memory_map
-            .expect("Frame allocator not initialized.")
-            .iter()
-            .filter(|r| r.region_type == MemoryRegionType::Usable)
-            .map(|r| r.range.start_addr()..r.range.end_addr())
-            .flat_map(|v| v.step_by(1024 * 4)) //4K
-            .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for MemoryFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
-    }
-}
-
 pub const PAGE_SIZE: usize = 4096; //4KiB
-lazy_static! {
-    pub static ref FRAME_ALLOCATOR: Mutex<MemoryFrameAllocator> =
-        Mutex::new(MemoryFrameAllocator::new());
-    static ref PHSYICAL_MEMORY_OFFSET: Mutex<u64> = Mutex::new(0);
-}
+pub static FRAME_ALLOCATOR: Mutex<MemoryFrameAllocator> = Mutex::new(MemoryFrameAllocator::new());
+
+/// Where the bootloader maps the physical memory, `physical-memory-offset` in Cargo.toml.
+/// The VGA buffer is reached through it before `init`.
+pub const PHYSICAL_MEMORY_OFFSET: u64 = 0xffff_8000_0000_0000;
 
 pub fn physical_memory_offset() -> u64 {
-    *PHSYICAL_MEMORY_OFFSET.lock()
+    PHYSICAL_MEMORY_OFFSET
 }
 
 //Must call after initializing heap.
@@ -75,58 +42,48 @@ pub fn empty_page_table() -> &'static mut PageTable {
     Box::leak(Box::new(PageTable::new()))
 }
 
-pub fn kernel_mapped_new_page_table() -> OffsetPageTable<'static> {
-    use crate::allocator::{HEAP_SIZE, HEAP_START};
-    use crate::memory::FRAME_ALLOCATOR;
-    use x86_64::structures::paging::{
-        mapper::{MappedFrame, TranslateResult},
-        PageTableFlags,
-    };
-
-    let phys_offset = VirtAddr::new(physical_memory_offset());
-    let new_page_table = empty_page_table();
-    let mut offset_page_table = unsafe { OffsetPageTable::new(new_page_table, phys_offset) };
-    let translator = unsafe { current_offset_page_table() };
-    let page_range = {
-        let kernel_start_addr = VirtAddr::new(0);
-        let kernel_end_addr = VirtAddr::new((HEAP_START + HEAP_SIZE) as u64);
-        let start_page = Page::containing_address(kernel_start_addr);
-        let end_page = Page::containing_address(kernel_end_addr);
-        Page::<Size4KiB>::range(start_page, end_page)
-    };
-
-    let offset = physical_memory_offset();
-    let offset_page_range = {
-        const MEMORY_SIZE: u64 = 0x8000000; // 128MiB
-        let start_virt = VirtAddr::new(offset);
-        let end_virt = VirtAddr::new(offset + MEMORY_SIZE);
-        let start_page = Page::containing_address(start_virt);
-        let end_page = Page::containing_address(end_virt);
-        Page::<Size4KiB>::range(start_page, end_page)
-    };
-    let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
-    let frame_allocator = frame_allocator_lock.get_mut();
-    for page in page_range {
-        if let TranslateResult::Mapped { frame, flags, .. } =
-            translator.translate(page.start_address())
-        {
-            if let MappedFrame::Size4KiB(frame) = frame {
-                unsafe { offset_page_table.map_to(page, frame, flags, frame_allocator) }
-                    .expect("map_to kernel page range failed.")
-                    .flush()
-            }
+/// The upper half of the address space belongs to the kernel: the bootloader maps the physical
+/// memory from `0xffff_8000_0000_0000` and the kernel image, the boot information and the boot
+/// stack in the last level 4 entry, the kernel heap and the kernel stacks each have a level 4
+/// entry of their own. Every level 4 entry of this half points to a table created at boot and
+/// shared by all page tables, so whatever the kernel maps later is seen in every address space.
+pub const KERNEL_PML4_START: usize = 256;
+
+/// The page table in `frame`, through the physical memory offset.
+fn frame_table(frame: PhysFrame) -> &'static mut PageTable {
+    let virt = frame.start_address().as_u64() + physical_memory_offset();
+    unsafe { &mut *(virt as *mut PageTable) }
+}
+
+/// Create the tables every level 4 entry of the kernel half points to, before any page
+/// table copies them, and keep user mode out of them. Nothing the kernel uses is left in the
+/// user half of the boot page table, whose mappings by the bootloader are dropped.
+pub fn init_kernel_space() {
+    let level_4_table = unsafe { active_level_4_table(VirtAddr::new(physical_memory_offset())) };
+    for (i, entry) in level_4_table.iter_mut().enumerate() {
+        if i < KERNEL_PML4_START {
+            entry.set_unused();
+        } else if entry.is_unused() {
+            let frame = alloc_frame().expect("Out of physical memory.");
+            frame_table(frame).zero();
+            entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        } else {
+            entry.set_flags(entry.flags() - PageTableFlags::USER_ACCESSIBLE);
         }
     }
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-    for page in offset_page_range {
-        let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(
-            page.start_address().as_u64() - offset,
-        ));
-        unsafe { offset_page_table.map_to(page, frame, flags, frame_allocator) }
-            .expect("map_to kernel page range failed.")
-            .flush()
+    x86_64::instructions::tlb::flush_all();
+}
+
+/// A page table sharing the kernel half of the active one, without copying any mapping,
+/// and with an empty user half.
+pub fn kernel_mapped_new_page_table() -> OffsetPageTable<'static> {
+    let phys_offset = VirtAddr::new(physical_memory_offset());
+    let active_table = unsafe { active_level_4_table(phys_offset) };
+    let new_table = empty_page_table();
+    for i in KERNEL_PML4_START..512 {
+        new_table[i] = active_table[i].clone();
     }
-    offset_page_table
+    unsafe { OffsetPageTable::new(new_table, phys_offset) }
 }
 
 pub fn init_frame_allocator(memory_map: &'static MemoryMap) {
@@ -137,6 +94,14 @@ pub fn alloc_frame() -> Option<PhysFrame> {
     FRAME_ALLOCATOR.lock().allocate_frame()
 }
 
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.lock().free_frames()
+}
+
+pub fn dealloc_frame(frame: PhysFrame) {
+    unsafe { FRAME_ALLOCATOR.lock().deallocate_frame(frame) }
+}
+
 pub fn create_example_mapping(
     page: Page,
     mapper: &mut OffsetPageTable,
@@ -150,7 +115,7 @@ pub fn create_example_mapping(
 }
 
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
-    *PHSYICAL_MEMORY_OFFSET.lock() = physical_memory_offset.as_u64();
+    assert_eq!(physical_memory_offset.as_u64(), PHYSICAL_MEMORY_OFFSET);
     let lv4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(lv4_table, physical_memory_offset)
 }
@@ -176,32 +141,13 @@ pub unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static
     &mut *page_table_ptr
 }
 
-pub const KERNEL_STACK_START: u64 = 0x4000000;
-pub const KERNEL_STACK_END: u64 = 0x4100000;
+/// Kernel stacks, picked by the tid of their task down from `KERNEL_STACK_END`, with a
+/// guard page below each.
+pub const KERNEL_STACK_START: u64 = 0xffff_8100_0000_0000;
+pub const KERNEL_STACK_END: u64 = 0xffff_8180_0000_0000;
 pub const KERNEL_STACK_SIZE: u64 = PAGE_SIZE as u64 * 7; // 28KiB
 pub const GUARD_SIZE: u64 = PAGE_SIZE as u64;
 
-pub fn init_kernel_stack(mapper: &mut OffsetPageTable) {
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-    let mut allocator = FRAME_ALLOCATOR.lock();
-    let allocator = allocator.get_mut();
-    //Left one page between each stack as guard.
-    for start in
-        (KERNEL_STACK_START..KERNEL_STACK_END).step_by((KERNEL_STACK_SIZE + GUARD_SIZE) as usize)
-    {
-        let page_range = Page::<Size4KiB>::range(
-            Page::containing_address(VirtAddr::new(start + GUARD_SIZE)),
-            Page::containing_address(VirtAddr::new(start + GUARD_SIZE + KERNEL_STACK_SIZE)),
-        );
-        for page in page_range {
-            let frame = allocator.allocate_frame().unwrap();
-            unsafe { mapper.map_to(page, frame, flags, allocator) }
-                .expect("map_to failed.")
-                .flush();
-        }
-    }
-}
-
 #[inline]
 pub fn get_app_kernel_stack(app_id: u64) -> u64 {
     use crate::memory::{GUARD_SIZE, KERNEL_STACK_END, KERNEL_STACK_SIZE};
@@ -0,0 +1,370 @@
+use super::memory_set::{MMAP_START, USER_START};
+use alloc::vec::Vec;
+use core::ops::Range;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+use xmas_elf::header::{self, Class, Data, Machine};
+use xmas_elf::program::{self, ProgramHeader64};
+use xmas_elf::ElfFile;
+
+/// Why an executable can not be loaded, reported as `ENOEXEC`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ElfLoadError {
+    BadMagic,
+    /// Not a little-endian 64-bit file.
+    WrongClass,
+    WrongMachine,
+    /// Neither an executable nor a position-independent one.
+    WrongType,
+    /// The headers or a segment lie past the end of the file.
+    Truncated,
+    /// A segment larger in the file than in memory, or without any loadable segment.
+    BadSegment,
+    OverlappingSegments,
+    /// A segment outside of the range user programs are loaded in, which the kernel, the
+    /// mmap range and the stacks lie above.
+    SegmentOutOfRange,
+    /// A malformed dynamic section, or a relocation of an unsupported type or outside of
+    /// the segments.
+    BadRelocation,
+    /// A malformed interpreter path, or an interpreter which is not position-independent or
+    /// asks for an interpreter itself.
+    BadInterpreter,
+}
+
+/// Lowest load address of position-independent executables, randomized up to
+/// `PIE_RANDOM_SIZE` higher.
+pub const PIE_BASE: usize = USER_START;
+pub const PIE_RANDOM_SIZE: usize = 0x8_0000_0000;
+
+/// Size of an `Elf64_Phdr` entry, as the auxiliary vector gives it.
+pub const PROGRAM_HEADER_SIZE: usize = core::mem::size_of::<ProgramHeader64>();
+
+const DT_NULL: u64 = 0;
+const DT_PLTRELSZ: u64 = 2;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_REL: u64 = 17;
+const DT_JMPREL: u64 = 23;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// Size of an `Elf64_Rela` entry.
+const RELA_SIZE: usize = 24;
+
+/// A loadable segment, to be mapped at `start` with `mem_size` bytes, the first ones copied
+/// from `file_range` of the file and the others zeroed.
+pub struct Segment {
+    pub start: VirtAddr,
+    pub mem_size: usize,
+    pub file_range: Range<usize>,
+    pub flags: PageTableFlags,
+}
+
+/// Set the word at `offset` to the load bias plus `addend`.
+pub struct Relocation {
+    pub offset: usize,
+    pub addend: usize,
+}
+
+/// A checked executable, with its loadable segments sorted by address. The addresses of a
+/// position-independent one are relative to where it is loaded.
+pub struct ElfImage<'a> {
+    pub data: &'a [u8],
+    pub entry_point: usize,
+    pub segments: Vec<Segment>,
+    pub position_independent: bool,
+    /// Empty with an interpreter, which relocates the executable itself.
+    pub relocations: Vec<Relocation>,
+    /// Path of the dynamic loader `PT_INTERP` asks for.
+    pub interpreter: Option<&'a str>,
+    /// Address of the program headers if a segment loads them.
+    pub program_headers: Option<usize>,
+    pub program_header_count: usize,
+}
+
+impl<'a> ElfImage<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfLoadError> {
+        // xmas-elf slices the input without checking its length.
+        let header_size = core::mem::size_of::<header::HeaderPt1>()
+            + core::mem::size_of::<header::HeaderPt2_<u64>>();
+        if data.len() < 4 {
+            return Err(ElfLoadError::Truncated);
+        }
+        if data[..4] != header::MAGIC {
+            return Err(ElfLoadError::BadMagic);
+        }
+        if data.len() < header_size {
+            return Err(ElfLoadError::Truncated);
+        }
+        let elf = ElfFile::new(data).map_err(|_| ElfLoadError::WrongClass)?;
+        let pt2 = &elf.header.pt2;
+        if elf.header.pt1.class() != Class::SixtyFour || elf.header.pt1.data() != Data::LittleEndian {
+            return Err(ElfLoadError::WrongClass);
+        }
+        if pt2.machine().as_machine() != Machine::X86_64 {
+            return Err(ElfLoadError::WrongMachine);
+        }
+        let position_independent = match pt2.type_().as_type() {
+            header::Type::Executable => false,
+            header::Type::SharedObject => true,
+            _ => return Err(ElfLoadError::WrongType),
+        };
+        if pt2.ph_count() == 0 || pt2.ph_entry_size() as usize != PROGRAM_HEADER_SIZE {
+            return Err(ElfLoadError::BadSegment);
+        }
+        let ph_end = (pt2.ph_offset() as usize)
+            .checked_add(pt2.ph_count() as usize * PROGRAM_HEADER_SIZE)
+            .ok_or(ElfLoadError::Truncated)?;
+        if ph_end > data.len() {
+            return Err(ElfLoadError::Truncated);
+        }
+
+        let mut segments = Vec::new();
+        let mut dynamic = None;
+        let mut interpreter = None;
+        for ph in elf.program_iter() {
+            let in_file = || {
+                let end = ph.offset().checked_add(ph.file_size());
+                if end.map_or(true, |end| end > data.len() as u64) {
+                    return Err(ElfLoadError::Truncated);
+                }
+                Ok(ph.offset() as usize..(ph.offset() + ph.file_size()) as usize)
+            };
+            if ph.get_type() == Ok(program::Type::Dynamic) {
+                dynamic = Some(in_file()?);
+            }
+            if ph.get_type() == Ok(program::Type::Interp) {
+                let path = match &data[in_file()?] {
+                    [path @ .., 0] if !path.contains(&0) => core::str::from_utf8(path).ok(),
+                    _ => None,
+                };
+                interpreter = Some(path.ok_or(ElfLoadError::BadInterpreter)?);
+            }
+            // Unknown types are ignored like the other ones that are not loaded.
+            if ph.get_type() != Ok(program::Type::Load) || ph.mem_size() == 0 {
+                continue;
+            }
+            if ph.file_size() > ph.mem_size() {
+                return Err(ElfLoadError::BadSegment);
+            }
+            let file_end = ph
+                .offset()
+                .checked_add(ph.file_size())
+                .ok_or(ElfLoadError::Truncated)?;
+            if file_end > data.len() as u64 {
+                return Err(ElfLoadError::Truncated);
+            }
+            // Wherever a position-independent executable is loaded, it stays in range.
+            let (lowest, highest) = if position_independent {
+                (0, MMAP_START - PIE_BASE - PIE_RANDOM_SIZE)
+            } else {
+                (USER_START, MMAP_START)
+            };
+            let end = ph.virtual_addr().checked_add(ph.mem_size());
+            if ph.virtual_addr() < lowest as u64 || end.map_or(true, |end| end > highest as u64) {
+                return Err(ElfLoadError::SegmentOutOfRange);
+            }
+            let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+            if !ph.flags().is_execute() {
+                flags |= PageTableFlags::NO_EXECUTE;
+            }
+            if ph.flags().is_write() {
+                flags |= PageTableFlags::WRITABLE;
+            }
+            segments.push(Segment {
+                start: VirtAddr::new(ph.virtual_addr()),
+                mem_size: ph.mem_size() as usize,
+                file_range: ph.offset() as usize..file_end as usize,
+                flags,
+            });
+        }
+        if segments.is_empty() {
+            return Err(ElfLoadError::BadSegment);
+        }
+        segments.sort_unstable_by_key(|segment| segment.start);
+        // Segments may share a page, but not a byte.
+        for pair in segments.windows(2) {
+            if pair[0].end() > pair[1].start {
+                return Err(ElfLoadError::OverlappingSegments);
+            }
+        }
+        let program_headers = segments.iter().find_map(|segment| {
+            let offset = (pt2.ph_offset() as usize).checked_sub(segment.file_range.start)?;
+            if ph_end > segment.file_range.end {
+                return None;
+            }
+            Some(segment.start.as_u64() as usize + offset)
+        });
+        let mut image = Self {
+            data,
+            entry_point: pt2.entry_point() as usize,
+            segments,
+            position_independent,
+            relocations: Vec::new(),
+            interpreter,
+            program_headers,
+            program_header_count: pt2.ph_count() as usize,
+        };
+        if let (true, None, Some(dynamic)) = (position_independent, interpreter, dynamic) {
+            image.read_relocations(&data[dynamic])?;
+        }
+        Ok(image)
+    }
+
+    /// Parse the interpreter of another executable, which is loaded wherever there is room
+    /// and relocated by the kernel.
+    pub fn parse_interpreter(data: &'a [u8]) -> Result<Self, ElfLoadError> {
+        let image = Self::parse(data)?;
+        if !image.position_independent || image.interpreter.is_some() {
+            return Err(ElfLoadError::BadInterpreter);
+        }
+        Ok(image)
+    }
+
+    /// Collect the relocations of the `RELA` and `JMPREL` tables listed in `dynamic`.
+    fn read_relocations(&mut self, dynamic: &[u8]) -> Result<(), ElfLoadError> {
+        let mut tables = [(None, 0), (None, 0)];
+        for entry in dynamic.chunks_exact(16) {
+            let tag = read_u64(entry, 0);
+            let value = read_u64(entry, 8);
+            match tag {
+                DT_NULL => break,
+                DT_RELA => tables[0].0 = Some(value),
+                DT_RELASZ => tables[0].1 = value as usize,
+                DT_JMPREL => tables[1].0 = Some(value),
+                DT_PLTRELSZ => tables[1].1 = value as usize,
+                DT_RELAENT if value as usize != RELA_SIZE => return Err(ElfLoadError::BadRelocation),
+                DT_REL => return Err(ElfLoadError::BadRelocation),
+                _ => {}
+            }
+        }
+        for &(addr, size) in tables.iter() {
+            let addr = match addr {
+                Some(addr) => addr as usize,
+                None => continue,
+            };
+            let table = self.file_bytes(addr, size).ok_or(ElfLoadError::BadRelocation)?;
+            for rela in table.chunks_exact(RELA_SIZE) {
+                let offset = read_u64(rela, 0) as usize;
+                let kind = read_u64(rela, 8) as u32;
+                let addend = read_u64(rela, 16) as usize;
+                match kind {
+                    R_X86_64_NONE => continue,
+                    R_X86_64_RELATIVE => {}
+                    _ => return Err(ElfLoadError::BadRelocation),
+                }
+                let in_segment = self.segments.iter().any(|segment| {
+                    let start = segment.start.as_u64() as usize;
+                    start <= offset && offset.checked_add(8).map_or(false, |end| end <= start + segment.mem_size)
+                });
+                if !in_segment {
+                    return Err(ElfLoadError::BadRelocation);
+                }
+                self.relocations.push(Relocation { offset, addend });
+            }
+        }
+        Ok(())
+    }
+
+    /// The `len` bytes of the file loaded at `addr`.
+    fn file_bytes(&self, addr: usize, len: usize) -> Option<&'a [u8]> {
+        self.segments.iter().find_map(|segment| {
+            let offset = addr.checked_sub(segment.start.as_u64() as usize)?;
+            if offset.checked_add(len)? > segment.file_range.len() {
+                return None;
+            }
+            let start = segment.file_range.start + offset;
+            Some(&self.data[start..start + len])
+        })
+    }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+impl Segment {
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.mem_size
+    }
+    /// The segment moved up by `bias`.
+    pub fn moved(&self, bias: usize) -> Segment {
+        Segment {
+            start: self.start + bias,
+            mem_size: self.mem_size,
+            file_range: self.file_range.clone(),
+            flags: self.flags,
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::{serial_print, serial_println};
+
+/// An executable with a text segment at 0x8000000 and a data segment at `data_start`.
+#[cfg(test)]
+fn test_elf(data_start: u64) -> [u8; 240] {
+    let mut elf = [0u8; 240];
+    let mut put = |offset: usize, bytes: &[u8]| elf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    put(0, &[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    put(16, &2u16.to_le_bytes());
+    put(18, &0x3eu16.to_le_bytes());
+    put(20, &1u32.to_le_bytes());
+    put(24, &0x8000000u64.to_le_bytes());
+    put(32, &64u64.to_le_bytes());
+    put(52, &64u16.to_le_bytes());
+    put(54, &56u16.to_le_bytes());
+    put(56, &2u16.to_le_bytes());
+    for (i, &(flags, start, file_size, mem_size)) in
+        [(5u32, 0x8000000u64, 240u64, 0x1800u64), (6, data_start, 0, 0x100)].iter().enumerate()
+    {
+        let ph = 64 + i * 56;
+        put(ph, &1u32.to_le_bytes());
+        put(ph + 4, &flags.to_le_bytes());
+        put(ph + 16, &start.to_le_bytes());
+        put(ph + 32, &file_size.to_le_bytes());
+        put(ph + 40, &mem_size.to_le_bytes());
+    }
+    elf
+}
+
+#[test_case]
+fn test_elf_image_rejects_bad_files() {
+    #[repr(align(8))]
+    struct Aligned([u8; 240]);
+    serial_print!("test_elf_image_rejects_bad_files -> ");
+    let parse = |elf: [u8; 240], len: usize| {
+        let aligned = Aligned(elf);
+        ElfImage::parse(&aligned.0[..len]).map(|image| image.segments.len())
+    };
+    // The data segment shares the last page of the text one.
+    assert_eq!(parse(test_elf(0x8001800), 240), Ok(2));
+    assert_eq!(parse(test_elf(0x80017ff), 240), Err(ElfLoadError::OverlappingSegments));
+    assert_eq!(parse(test_elf(0xffff_8000_0000_0000), 240), Err(ElfLoadError::SegmentOutOfRange));
+    assert_eq!(parse(test_elf(0x8001800), 200), Err(ElfLoadError::Truncated));
+    assert_eq!(parse(test_elf(0x8001800), 40), Err(ElfLoadError::Truncated));
+    let mut elf = test_elf(0x8001800);
+    elf[0] = 0;
+    assert_eq!(parse(elf, 240), Err(ElfLoadError::BadMagic));
+    let mut elf = test_elf(0x8001800);
+    elf[4] = 1;
+    assert_eq!(parse(elf, 240), Err(ElfLoadError::WrongClass));
+    let mut elf = test_elf(0x8001800);
+    elf[18] = 0x28;
+    assert_eq!(parse(elf, 240), Err(ElfLoadError::WrongMachine));
+    let mut elf = test_elf(0x8001800);
+    elf[16] = 1;
+    assert_eq!(parse(elf, 240), Err(ElfLoadError::WrongType));
+    // Position-independent, the same addresses are relative to the load base.
+    elf[16] = 3;
+    assert_eq!(parse(elf, 240), Ok(2));
+    // The text segment loads the headers after the file header.
+    let aligned = Aligned(test_elf(0x8001800));
+    assert_eq!(ElfImage::parse(&aligned.0).map(|image| image.program_headers), Ok(Some(0x8000040)));
+    serial_println!("[ok]");
+}
@@ -0,0 +1,146 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr,
+};
+
+use crate::memory::{dealloc_frame, physical_memory_offset, PAGE_SIZE};
+
+pub const MAX_PHYSICAL_MEMORY: u64 = 0x1_0000_0000; // 4GiB
+const MAX_FRAMES: usize = (MAX_PHYSICAL_MEMORY / PAGE_SIZE as u64) as usize;
+const LEVEL0_WORDS: usize = MAX_FRAMES / 64;
+const LEVEL1_WORDS: usize = LEVEL0_WORDS / 64;
+const LEVEL2_WORDS: usize = LEVEL1_WORDS / 64;
+
+/// Three level bitmap of physical frames.
+///
+/// A set bit in `level0` marks a free frame, a set bit in `level1` marks a `level0` word
+/// which still contains free frames and `level2` does the same for `level1`,
+/// so both allocation and deallocation only touch one word per level.
+pub struct MemoryFrameAllocator {
+    level0: [u64; LEVEL0_WORDS],
+    level1: [u64; LEVEL1_WORDS],
+    level2: [u64; LEVEL2_WORDS],
+    free_frames: usize,
+}
+
+impl MemoryFrameAllocator {
+    pub const fn new() -> Self {
+        MemoryFrameAllocator {
+            level0: [0; LEVEL0_WORDS],
+            level1: [0; LEVEL1_WORDS],
+            level2: [0; LEVEL2_WORDS],
+            free_frames: 0,
+        }
+    }
+    pub fn get_mut(&mut self) -> &mut Self {
+        self
+    }
+    pub unsafe fn init(&mut self, memory_map: &'static MemoryMap) {
+        memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| r.range.start_frame_number..r.range.end_frame_number)
+            .flatten()
+            .filter(|&number| (number as usize) < MAX_FRAMES)
+            .for_each(|number| self.release(number as usize));
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    fn is_free(&self, number: usize) -> bool {
+        self.level0[number / 64] & (1 << (number % 64)) != 0
+    }
+
+    fn release(&mut self, number: usize) {
+        assert!(
+            !self.is_free(number),
+            "frame {:#x} has been deallocated.",
+            number * PAGE_SIZE
+        );
+        self.level0[number / 64] |= 1 << (number % 64);
+        self.level1[number / 64 / 64] |= 1 << (number / 64 % 64);
+        self.level2[number / 64 / 64 / 64] |= 1 << (number / 64 / 64 % 64);
+        self.free_frames += 1;
+    }
+
+    fn acquire(&mut self) -> Option<usize> {
+        let i2 = self.level2.iter().position(|&word| word != 0)?;
+        let i1 = i2 * 64 + self.level2[i2].trailing_zeros() as usize;
+        let i0 = i1 * 64 + self.level1[i1].trailing_zeros() as usize;
+        let number = i0 * 64 + self.level0[i0].trailing_zeros() as usize;
+
+        self.level0[i0] &= !(1 << (number % 64));
+        if self.level0[i0] == 0 {
+            self.level1[i1] &= !(1 << (i0 % 64));
+            if self.level1[i1] == 0 {
+                self.level2[i2] &= !(1 << (i1 % 64));
+            }
+        }
+        self.free_frames -= 1;
+        Some(number)
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for MemoryFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.acquire()
+            .map(|number| PhysFrame::containing_address(PhysAddr::new((number * PAGE_SIZE) as u64)))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for MemoryFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.release(frame.start_address().as_u64() as usize / PAGE_SIZE)
+    }
+}
+
+/// A zeroed frame which is given back to `FRAME_ALLOCATOR` when dropped.
+pub struct FrameTracker {
+    pub frame: PhysFrame,
+}
+
+impl FrameTracker {
+    pub fn new(frame: PhysFrame) -> Self {
+        let tracker = Self { frame };
+        tracker.as_slice_mut().fill(0);
+        tracker
+    }
+
+    pub fn as_slice_mut(&self) -> &'static mut [u8] {
+        let virt = self.frame.start_address().as_u64() + physical_memory_offset();
+        unsafe { core::slice::from_raw_parts_mut(virt as usize as *mut u8, PAGE_SIZE) }
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        dealloc_frame(self.frame);
+    }
+}
+
+#[cfg(test)]
+use crate::{serial_print, serial_println};
+
+#[test_case]
+fn test_bitmap_reuses_released_frames() {
+    use spin::Mutex;
+    static ALLOCATOR: Mutex<MemoryFrameAllocator> = Mutex::new(MemoryFrameAllocator::new());
+    serial_print!("test_bitmap_reuses_released_frames -> ");
+    let mut allocator = ALLOCATOR.lock();
+    (0x100..0x200).for_each(|number| allocator.release(number));
+    assert_eq!(allocator.free_frames(), 0x100);
+
+    let first = allocator.allocate_frame().unwrap();
+    let second = allocator.allocate_frame().unwrap();
+    assert_eq!(first.start_address().as_u64(), 0x100 * PAGE_SIZE as u64);
+    assert_ne!(first, second);
+    unsafe { allocator.deallocate_frame(first) };
+    assert_eq!(allocator.allocate_frame(), Some(first));
+
+    while allocator.allocate_frame().is_some() {}
+    assert_eq!(allocator.free_frames(), 0);
+    serial_println!("[ok]");
+}
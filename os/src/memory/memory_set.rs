@@ -1,3 +1,5 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use x86_64::{
     structures::paging::{
@@ -8,21 +10,98 @@ use x86_64::{
 };
 
 use super::active_level_4_table;
-use crate::memory::{empty_page_table, physical_memory_offset, PAGE_SIZE};
+use super::elf::{ElfImage, Segment, PIE_BASE, PIE_RANDOM_SIZE};
+use crate::fs::Inode;
+use crate::memory::frame_allocator::FrameTracker;
+use crate::memory::{
+    dealloc_frame, empty_page_table, physical_memory_offset, KERNEL_PML4_START, PAGE_SIZE,
+};
 use lazy_static::lazy_static;
 use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
 use x86_64::structures::paging::mapper::TranslateError::PageNotMapped;
-use x86_64::structures::paging::PageTable;
+use x86_64::structures::paging::{PageTable, PhysFrame};
 
-pub const KERNEL_START: usize = 0x0;
+/// Start of the kernel half, see `KERNEL_PML4_START`.
+pub const KERNEL_START: usize = 0xffff_8000_0000_0000;
 pub const USER_START: usize = 0x8000000;
 pub const USER_STACK_SIZE: usize = 1024 * 1024; //1MB
+/// The main user stack grows down on faults until it is this large, the page below is
+/// left unmapped as a guard.
+pub const USER_STACK_LIMIT: usize = 8 * 1024 * 1024;
+/// Highest top of the main user stack, which is randomized up to `STACK_RANDOM_SIZE` lower.
+pub const USER_STACK_TOP: usize = 0x7f_ffff_f000;
+pub const STACK_RANDOM_SIZE: usize = 0x4000_0000;
+/// The heap starts up to this far after the executable.
+pub const HEAP_RANDOM_SIZE: usize = 0x200_0000;
+/// End of the user space, which stays in the first entry of the level 4 table, far below
+/// the kernel half.
+pub const USER_END: usize = 0x80_0000_0000;
+/// Range `find_free_range` picks mappings from.
+pub const MMAP_START: usize = 0x10_0000_0000;
+pub const MMAP_END: usize = 0x30_0000_0000;
+/// Marks a page whose frame is shared after `fork` and must be copied on the first write.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Flags of user pages with the `PROT_*` protection `prot`. Pages cannot be write or
+/// execute only, and `PROT_NONE` pages are kept present but out of reach of user mode.
+pub fn prot_flags(prot: usize) -> Option<PageTableFlags> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return None;
+    }
+    let mut flags = PageTableFlags::PRESENT;
+    if prot != 0 {
+        flags |= PageTableFlags::USER_ACCESSIBLE;
+    }
+    if prot & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Some(flags)
+}
+
+/// Where `read_elf` loaded a program, which the auxiliary vector describes to it.
+pub struct ElfLayout {
+    pub stack_top: usize,
+    /// Where execution starts, in the interpreter if there is one.
+    pub start: usize,
+    /// Entry point of the program itself.
+    pub entry_point: usize,
+    /// Address of the program headers, 0 if they are not loaded.
+    pub program_headers: usize,
+    pub program_header_count: usize,
+    /// Where the interpreter is loaded, 0 without one.
+    pub interpreter_base: usize,
+}
+
+/// File an area maps, from `offset` on for its first page.
+#[derive(Clone)]
+pub struct FileBacking {
+    pub inode: Arc<dyn Inode>,
+    pub offset: usize,
+}
 
 pub struct MapArea {
     page_range: PageRangeInclusive,
     start_virt_addr: VirtAddr,
     end_virt_addr: VirtAddr,
     flags: PageTableFlags,
+    frames: BTreeMap<Page, Arc<FrameTracker>>,
+    /// Frames stay shared after `fork` instead of being copied on write.
+    pub shared: bool,
+    /// Shared file mappings are written back to the file when they are unmapped.
+    pub backing: Option<FileBacking>,
 }
 
 impl MapArea {
@@ -34,6 +113,9 @@ impl MapArea {
             start_virt_addr,
             end_virt_addr,
             flags,
+            frames: BTreeMap::new(),
+            shared: false,
+            backing: None,
         }
     }
 
@@ -43,6 +125,9 @@ impl MapArea {
             start_virt_addr: other.start_virt_addr,
             end_virt_addr: other.end_virt_addr,
             flags: other.flags,
+            frames: BTreeMap::new(),
+            shared: other.shared,
+            backing: other.backing.clone(),
         }
     }
     pub fn map(&mut self, page_table: &mut OffsetPageTable) {
@@ -52,32 +137,51 @@ impl MapArea {
     }
 
     pub fn unmap(&mut self, page_table: &mut OffsetPageTable) {
-        for page in self.page_range {
-            page_table.unmap(page);
-        }
+        self.write_back();
+        self.unmap_pages(self.page_range, page_table);
     }
 
+    /// Copy `data` to the start of the area, whose pages holding it must be mapped.
     pub fn copy_data(&mut self, page_table: &mut OffsetPageTable, data: &[u8]) {
-        let len = data.len();
-        let physical_memory_offset = physical_memory_offset();
-        let mut start_virt = self.start_virt_addr;
-        let end_virtual = self.end_virt_addr;
-        for page in self.page_range {
-            let start = page.start_address().as_u64() as usize
-                - self.page_range.start.start_address().as_u64() as usize;
-            let src = &data[start..len.min(start + 4096)];
-            let dst = unsafe {
-                let mut dst = page_table.translate_addr(start_virt).unwrap().as_u64();
-                dst += physical_memory_offset;
-                core::slice::from_raw_parts_mut(dst as usize as *mut u8, src.len())
-            };
-            dst.copy_from_slice(src);
-            start_virt += PAGE_SIZE;
-            if start_virt >= end_virtual {
-                break;
-            }
+        let mut addr = self.start_virt_addr;
+        let mut copied = 0;
+        while copied < data.len() {
+            let page_offset = addr.as_u64() as usize % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(data.len() - copied);
+            copy_to_page(page_table, addr, &data[copied..copied + len]);
+            copied += len;
+            addr += len;
         }
     }
+
+    /// Map the pages holding `data` and copy it to the start of the area, the other pages
+    /// are left to `fault_in`.
+    pub fn map_data(&mut self, page_table: &mut OffsetPageTable, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let last_page = Page::containing_address(self.start_virt_addr + (data.len() - 1));
+        for page in Page::range_inclusive(self.page_range.start, last_page) {
+            self.map_one(page, page_table);
+        }
+        self.copy_data(page_table, data);
+    }
+}
+
+/// A random multiple of the page size below `size`, 0 with the `norandmaps` boot option.
+fn random_offset(size: usize) -> usize {
+    use crate::cmdline::has_option;
+    use crate::random::random_below;
+    if has_option("norandmaps") {
+        return 0;
+    }
+    random_below((size / PAGE_SIZE) as u64) as usize * PAGE_SIZE
+}
+
+/// Copy `data` to `addr` through the physical memory offset, within a mapped page.
+fn copy_to_page(page_table: &OffsetPageTable, addr: VirtAddr, data: &[u8]) {
+    let dst = page_table.translate_addr(addr).unwrap().as_u64() + physical_memory_offset();
+    unsafe { core::slice::from_raw_parts_mut(dst as usize as *mut u8, data.len()) }.copy_from_slice(data);
 }
 
 impl MapArea {
@@ -87,11 +191,17 @@ impl MapArea {
 
         match page_table.translate_page(page) {
             Err(PageNotMapped) => {
-                let frame = alloc_frame().unwrap();
+                let frame = FrameTracker::new(alloc_frame().expect("Out of physical memory."));
                 let map_result = unsafe {
-                    page_table.map_to(page, frame, self.flags, FRAME_ALLOCATOR.lock().get_mut())
+                    page_table.map_to(
+                        page,
+                        frame.frame,
+                        self.flags,
+                        FRAME_ALLOCATOR.lock().get_mut(),
+                    )
                 };
                 map_result.expect("Map failed.").flush();
+                self.frames.insert(page, Arc::new(frame));
             }
             Ok(frame) => {
                 // crate::println!("Already map: {:?} -> {:?}", page, frame)
@@ -111,9 +221,230 @@ impl MapArea {
     }
 }
 
+impl MapArea {
+    /// Move the end of the area to `end`, which is page aligned. New pages are left to
+    /// `fault_in`, the pages past `end` are unmapped.
+    pub fn resize(&mut self, end: VirtAddr, page_table: &mut OffsetPageTable) {
+        let last_page = Page::containing_address(end) - 1;
+        if last_page < self.page_range.end {
+            self.unmap_pages(Page::range_inclusive(last_page + 1, self.page_range.end), page_table);
+        }
+        self.page_range.end = last_page;
+        self.end_virt_addr = end;
+    }
+
+    /// Move the start of the area down to `page`, the new pages are left to `fault_in`.
+    fn grow_down(&mut self, page: Page) {
+        self.page_range.start = page;
+        self.start_virt_addr = page.start_address();
+    }
+
+    /// Map a zeroed frame, or one filled from the file, at `page` on its first access.
+    /// False if the access is not allowed or there are no frames left.
+    fn fault_in(&mut self, page: Page, write: bool, page_table: &mut OffsetPageTable) -> bool {
+        use crate::memory::{alloc_frame, FRAME_ALLOCATOR};
+        if !self.flags.contains(PageTableFlags::USER_ACCESSIBLE)
+            || (write && !self.flags.contains(PageTableFlags::WRITABLE))
+        {
+            return false;
+        }
+        let frame = match alloc_frame() {
+            Some(frame) => FrameTracker::new(frame),
+            None => return false,
+        };
+        self.load_page(page, &frame);
+        let map_result = unsafe {
+            page_table.map_to(page, frame.frame, self.flags, FRAME_ALLOCATOR.lock().get_mut())
+        };
+        // The page tables may need frames too.
+        match map_result {
+            Ok(flush) => flush.flush(),
+            Err(MapToError::FrameAllocationFailed) => return false,
+            Err(error) => panic!("Map failed: {:?}", error),
+        }
+        self.frames.insert(page, Arc::new(frame));
+        true
+    }
+
+    /// Fill the mapped pages with the file contents, zero past its end.
+    pub fn load_file(&self) {
+        for (&page, frame) in self.frames.iter() {
+            self.load_page(page, frame);
+        }
+    }
+
+    fn load_page(&self, page: Page, frame: &FrameTracker) {
+        if let Some(backing) = &self.backing {
+            let offset = backing.offset + self.offset_of(page);
+            let _ = backing.inode.read_at(offset, frame.as_slice_mut());
+        }
+    }
+
+    /// Write the pages of a shared file mapping back to the file, without growing it.
+    fn write_back(&self) {
+        let backing = match &self.backing {
+            Some(backing) if self.shared => backing,
+            _ => return,
+        };
+        let size = backing.inode.stat().size as usize;
+        for (&page, frame) in self.frames.iter() {
+            let offset = backing.offset + self.offset_of(page);
+            if offset < size {
+                let len = (size - offset).min(PAGE_SIZE);
+                let _ = backing.inode.write_at(offset, &frame.as_slice_mut()[..len]);
+            }
+        }
+    }
+
+    fn offset_of(&self, page: Page) -> usize {
+        (page.start_address() - self.page_range.start.start_address()) as usize
+    }
+
+    /// Cut the area before `at`, one of its pages but the first, returning the upper part.
+    fn split_off(&mut self, at: Page) -> MapArea {
+        let backing = self.backing.clone().map(|backing| FileBacking {
+            inode: backing.inode,
+            offset: backing.offset + self.offset_of(at),
+        });
+        let upper = MapArea {
+            page_range: PageRangeInclusive {
+                start: at,
+                end: self.page_range.end,
+            },
+            start_virt_addr: at.start_address(),
+            end_virt_addr: self.end_virt_addr,
+            flags: self.flags,
+            frames: self.frames.split_off(&at),
+            shared: self.shared,
+            backing,
+        };
+        self.page_range.end = at - 1;
+        self.end_virt_addr = at.start_address();
+        upper
+    }
+
+    /// Change the flags of every page. Private frames still shared after `fork` stay
+    /// read-only until they are copied.
+    fn set_flags(&mut self, flags: PageTableFlags, page_table: &mut OffsetPageTable) {
+        self.flags = flags;
+        for (&page, frame) in self.frames.iter() {
+            let copy_on_write =
+                !self.shared && flags.contains(PageTableFlags::WRITABLE) && Arc::strong_count(frame) > 1;
+            let page_flags = if copy_on_write {
+                (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE
+            } else {
+                flags
+            };
+            unsafe { page_table.update_flags(page, page_flags) }
+                .expect("Update flags failed.")
+                .flush();
+        }
+    }
+
+    /// Unmap the pages of `pages` that have a frame.
+    fn unmap_pages(&mut self, pages: PageRangeInclusive, page_table: &mut OffsetPageTable) {
+        if pages.is_empty() {
+            return;
+        }
+        let mapped: Vec<Page> = self.frames.range(pages.start..=pages.end).map(|(&page, _)| page).collect();
+        for page in mapped {
+            if let Ok((_, flush)) = page_table.unmap(page) {
+                flush.flush();
+            }
+            self.frames.remove(&page);
+        }
+    }
+
+    fn contains(&self, page: Page) -> bool {
+        self.page_range.start <= page && page <= self.page_range.end
+    }
+
+    /// Map every frame of this area into `target_page_table` as well. Unless the area is
+    /// shared, both copies are read-only and marked `COPY_ON_WRITE` if it is writable.
+    /// None if the page tables of `target_page_table` run out of frames.
+    pub fn share_copy_on_write(
+        &mut self,
+        page_table: &mut OffsetPageTable,
+        target_page_table: &mut OffsetPageTable,
+    ) -> Option<MapArea> {
+        use crate::memory::FRAME_ALLOCATOR;
+        let mut new_area = MapArea::from(self);
+        let flags = if !self.shared && self.flags.contains(PageTableFlags::WRITABLE) {
+            (self.flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE
+        } else {
+            self.flags
+        };
+        for (&page, frame) in self.frames.iter() {
+            if flags != self.flags {
+                unsafe { page_table.update_flags(page, flags) }
+                    .expect("Update flags failed.")
+                    .flush();
+            }
+            let map_result = unsafe {
+                target_page_table.map_to(page, frame.frame, flags, FRAME_ALLOCATOR.lock().get_mut())
+            };
+            match map_result {
+                Ok(flush) => flush.flush(),
+                Err(MapToError::FrameAllocationFailed) => {
+                    let pages = new_area.page_range;
+                    new_area.unmap_pages(pages, target_page_table);
+                    return None;
+                }
+                Err(error) => panic!("Map failed: {:?}", error),
+            }
+            new_area.frames.insert(page, frame.clone());
+        }
+        Some(new_area)
+    }
+
+    /// Give `page` a private writable frame if it is a copy-on-write page of this area.
+    /// The frame is only copied while it is still shared with another address space.
+    /// False as well if there are no frames left for the copy.
+    pub fn resolve_copy_on_write(&mut self, page: Page, page_table: &mut OffsetPageTable) -> bool {
+        use crate::memory::{alloc_frame, FRAME_ALLOCATOR};
+        use x86_64::structures::paging::mapper::TranslateResult;
+        match page_table.translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } if flags.contains(COPY_ON_WRITE) => {}
+            _ => return false,
+        }
+        let frame = match self.frames.get_mut(&page) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        if Arc::strong_count(frame) == 1 {
+            unsafe { page_table.update_flags(page, self.flags) }
+                .expect("Update flags failed.")
+                .flush();
+        } else {
+            let copy = match alloc_frame() {
+                Some(copy) => FrameTracker::new(copy),
+                None => return false,
+            };
+            copy.as_slice_mut().copy_from_slice(frame.as_slice_mut());
+            page_table.unmap(page).expect("Unmap failed.").1.flush();
+            let map_result = unsafe {
+                page_table.map_to(page, copy.frame, self.flags, FRAME_ALLOCATOR.lock().get_mut())
+            };
+            match map_result {
+                Ok(flush) => flush.flush(),
+                Err(MapToError::FrameAllocationFailed) => return false,
+                Err(error) => panic!("Map failed: {:?}", error),
+            }
+            *frame = Arc::new(copy);
+        }
+        true
+    }
+}
+
 pub struct MemorySet {
     pub page_table: OffsetPageTable<'static>,
     pub areas: Vec<MapArea>,
+    /// Start of the heap area, right after the ELF segments.
+    pub heap_start: usize,
+    /// Program break, the end of the heap as `brk` set it.
+    pub brk: usize,
+    /// Top of the main stack, 0 without one.
+    pub stack_top: usize,
 }
 
 impl MemorySet {
@@ -122,6 +453,9 @@ impl MemorySet {
         Self {
             page_table: kernel_mapped_new_page_table(),
             areas: Vec::new(),
+            heap_start: 0,
+            brk: 0,
+            stack_top: 0,
         }
     }
     fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
@@ -142,6 +476,11 @@ impl MemorySet {
         self.push(MapArea::new(start_virt_addr, end_virt_addr, flags), data)
     }
 
+    /// Add an area whose pages are only mapped on their first access.
+    pub fn reserve(&mut self, start_virt_addr: VirtAddr, end_virt_addr: VirtAddr, flags: PageTableFlags) {
+        self.areas.push(MapArea::new(start_virt_addr, end_virt_addr, flags));
+    }
+
     pub fn page_table_address(&mut self, translator: &OffsetPageTable) -> usize {
         use x86_64::structures::paging::PageTable;
         let lv4_table: *const PageTable = self.page_table.level_4_table();
@@ -171,102 +510,419 @@ impl MemorySet {
         }
     }
 }
+impl MemorySet {
+    /// Give back the frames of every level 3, 2 and 1 table of the user half reachable from
+    /// the level 4 table, the kernel half is shared. Leaf frames are owned by the areas and
+    /// are not touched here.
+    fn dealloc_page_tables(&mut self) {
+        let offset = physical_memory_offset();
+        let table = |frame: PhysFrame| unsafe {
+            &*((frame.start_address().as_u64() + offset) as usize as *const PageTable)
+        };
+        let user_half = self.page_table.level_4_table().iter().take(KERNEL_PML4_START);
+        for l4_entry in user_half {
+            if let Ok(l3_frame) = l4_entry.frame() {
+                for l3_entry in table(l3_frame).iter() {
+                    if let Ok(l2_frame) = l3_entry.frame() {
+                        for l2_entry in table(l2_frame).iter() {
+                            if let Ok(l1_frame) = l2_entry.frame() {
+                                dealloc_frame(l1_frame);
+                            }
+                        }
+                        dealloc_frame(l2_frame);
+                    }
+                }
+                dealloc_frame(l3_frame);
+            }
+        }
+    }
+}
+
 impl Drop for MemorySet {
     fn drop(&mut self) {
         use alloc::boxed::Box;
+        self.remove_all_areas();
+        self.dealloc_page_tables();
         unsafe { Box::from_raw(self.page_table.level_4_table() as *mut PageTable) };
     }
 }
 
 impl MemorySet {
-    pub fn from(user_space: &MemorySet) -> Self {
+    /// A copy-on-write copy of `user_space`, None if there are not enough frames for its
+    /// page tables.
+    pub fn from(user_space: &mut MemorySet) -> Option<Self> {
         let mut memory_set = Self::new();
-        let physical_memory_offset = physical_memory_offset();
-        for area in user_space.areas.iter() {
-            let mut new_area = MapArea::from(area);
-            memory_set.push(new_area, None);
-            for page in area.page_range {
-                let src = {
-                    let mut src = user_space
-                        .page_table
-                        .translate_page(page)
-                        .unwrap()
-                        .start_address()
-                        .as_u64();
-                    src += physical_memory_offset;
-                    let len = 4096.min(area.end_virt_addr - page.start_address()) as usize;
-                    unsafe { core::slice::from_raw_parts(src as usize as *const u8, len) }
-                };
-                let dst = unsafe {
-                    let mut dst = memory_set
-                        .page_table
-                        .translate_page(page)
-                        .unwrap()
-                        .start_address()
-                        .as_u64();
-                    dst += physical_memory_offset;
-                    core::slice::from_raw_parts_mut(dst as usize as *mut u8, src.len())
-                };
-                dst.copy_from_slice(src);
+        for area in user_space.areas.iter_mut() {
+            let new_area =
+                area.share_copy_on_write(&mut user_space.page_table, &mut memory_set.page_table)?;
+            memory_set.areas.push(new_area);
+        }
+        memory_set.heap_start = user_space.heap_start;
+        memory_set.brk = user_space.brk;
+        memory_set.stack_top = user_space.stack_top;
+        Some(memory_set)
+    }
+
+    /// Move the program break to `brk`, growing or shrinking the heap area. False if `brk`
+    /// is below the heap, if the heap would run into another area or if memory runs out.
+    pub fn set_brk(&mut self, brk: usize) -> bool {
+        let heap_start = VirtAddr::new(self.heap_start as u64);
+        if brk < self.heap_start || VirtAddr::try_new(brk as u64).is_err() {
+            return false;
+        }
+        let end = VirtAddr::new(brk as u64).align_up(PAGE_SIZE as u64);
+        let last_page = Page::containing_address(end) - 1;
+        let overlaps = self.areas.iter().any(|area| {
+            area.start_virt_addr != heap_start
+                && area.page_range.start <= last_page
+                && Page::containing_address(heap_start) <= area.page_range.end
+        });
+        if overlaps {
+            return false;
+        }
+        let page_table = &mut self.page_table;
+        let heap = match self.areas.iter_mut().find(|area| area.start_virt_addr == heap_start) {
+            Some(heap) => heap,
+            // Unmapped by munmap.
+            None => return false,
+        };
+        heap.resize(end, page_table);
+        self.brk = brk;
+        true
+    }
+
+    /// Lowest free range of `len` bytes in the mmap range, or at `hint` if it is free.
+    pub fn find_free_range(&self, len: usize, hint: Option<VirtAddr>) -> Option<VirtAddr> {
+        let len = len as u64;
+        let mut used: Vec<(u64, u64)> = self
+            .areas
+            .iter()
+            .filter(|area| !area.page_range.is_empty())
+            .map(|area| {
+                let start = area.page_range.start.start_address().as_u64();
+                (start, area.page_range.end.start_address().as_u64() + PAGE_SIZE as u64)
+            })
+            .collect();
+        // Where the main stack may grow, with its guard page.
+        if self.stack_top != 0 {
+            used.push((
+                (self.stack_top - USER_STACK_LIMIT - PAGE_SIZE) as u64,
+                (self.stack_top + PAGE_SIZE) as u64,
+            ));
+        }
+        used.sort_unstable();
+        let is_free = |start: u64| used.iter().all(|&(s, e)| start + len <= s || e <= start);
+        if let Some(hint) = hint {
+            let hint = hint.align_down(PAGE_SIZE as u64).as_u64();
+            if hint >= USER_START as u64
+                && hint.checked_add(len).map_or(false, |end| end <= USER_END as u64)
+                && is_free(hint)
+            {
+                return Some(VirtAddr::new(hint));
             }
         }
-        memory_set
+        let mut start = MMAP_START as u64;
+        for &(s, e) in used.iter() {
+            if e <= start {
+                continue;
+            }
+            if start + len <= s {
+                break;
+            }
+            start = e;
+        }
+        if start.checked_add(len)? <= MMAP_END as u64 {
+            Some(VirtAddr::new(start))
+        } else {
+            None
+        }
     }
-    pub fn read_elf(&mut self, elf_data: &[u8]) -> (usize, usize) {
-        let elf = xmas_elf::ElfFile::new(elf_data).expect("invalid elf!");
-        let elf_header = elf.header;
-        assert_eq!(
-            elf.header.pt1.magic,
-            [0x7f, 0x45, 0x4c, 0x46],
-            "invalid elf!"
-        );
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_page = Page::containing_address(VirtAddr::new(0));
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_virt_addr = VirtAddr::new(ph.virtual_addr());
-                let end_virt_addr = VirtAddr::new(ph.virtual_addr() + ph.mem_size());
-                let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
-                let ph_flag = ph.flags();
-                if !ph_flag.is_execute() {
-                    flags |= PageTableFlags::NO_EXECUTE
-                }
-                if ph_flag.is_write() {
-                    flags |= PageTableFlags::WRITABLE
-                }
-                let map_area = MapArea::new(start_virt_addr, end_virt_addr, flags);
-                max_page = map_area.page_range.end;
-                self.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+
+    /// Map `len` bytes from the page aligned `start`, zero-filled or filled from `backing`.
+    /// Private pages are mapped on their first access, shared ones at once so that `fork`
+    /// shares their frames. False if there are not enough frames.
+    pub fn map_region(
+        &mut self,
+        start: VirtAddr,
+        len: usize,
+        flags: PageTableFlags,
+        shared: bool,
+        backing: Option<FileBacking>,
+    ) -> bool {
+        use crate::memory::free_frames;
+        let mut area = MapArea::new(start, start + (len - 1), flags);
+        area.shared = shared;
+        area.backing = backing;
+        if shared {
+            // Page tables need frames too.
+            if (len + PAGE_SIZE - 1) / PAGE_SIZE + 16 > free_frames() {
+                return false;
             }
+            area.map(&mut self.page_table);
+            area.load_file();
         }
-        let mut user_stack_bottom = max_page.start_address();
-        user_stack_bottom += 4096u64; //Guard Page
-
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        self.push(
-            MapArea::new(
-                user_stack_bottom,
-                user_stack_top,
-                PageTableFlags::PRESENT
-                    | PageTableFlags::WRITABLE
-                    | PageTableFlags::USER_ACCESSIBLE,
-            ),
-            None,
+        self.areas.push(area);
+        true
+    }
+
+    /// Make an area start at `page` if one contains it.
+    fn split_at(&mut self, page: Page) {
+        if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.page_range.start < page && page <= area.page_range.end)
+        {
+            let upper = area.split_off(page);
+            self.areas.push(upper);
+        }
+    }
+
+    /// Split the areas at the bounds of the page aligned range `start..end`, returning the
+    /// pages of the range.
+    fn split_range(&mut self, start: VirtAddr, end: VirtAddr) -> (Page, Page) {
+        let first = Page::containing_address(start);
+        let last = Page::containing_address(end - 1u64);
+        self.split_at(first);
+        self.split_at(last + 1);
+        (first, last)
+    }
+
+    /// Unmap the page aligned range `start..end`, splitting the areas it cuts.
+    pub fn unmap_range(&mut self, start: VirtAddr, end: VirtAddr) {
+        let (first, last) = self.split_range(start, end);
+        let mut i = 0;
+        while i < self.areas.len() {
+            let range = self.areas[i].page_range;
+            if !range.is_empty() && first <= range.start && range.end <= last {
+                self.areas.remove(i).unmap(&mut self.page_table);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Change the flags of the page aligned range `start..end`, false without any change if
+    /// some of its pages are not mapped.
+    pub fn protect_range(&mut self, start: VirtAddr, end: VirtAddr, flags: PageTableFlags) -> bool {
+        let first = Page::containing_address(start);
+        let last = Page::containing_address(end - 1u64);
+        let mut covered: Vec<PageRangeInclusive> = self
+            .areas
+            .iter()
+            .map(|area| area.page_range)
+            .filter(|range| !range.is_empty() && range.start <= last && first <= range.end)
+            .collect();
+        covered.sort_unstable_by_key(|range| range.start);
+        let mut next = first;
+        for range in covered {
+            if range.start > next {
+                return false;
+            }
+            next = next.max(range.end + 1);
+        }
+        if next <= last {
+            return false;
+        }
+        self.split_range(start, end);
+        let page_table = &mut self.page_table;
+        self.areas
+            .iter_mut()
+            .filter(|area| first <= area.page_range.start && area.page_range.end <= last)
+            .filter(|area| !area.page_range.is_empty())
+            .for_each(|area| area.set_flags(flags, page_table));
+        true
+    }
+
+    /// Handle a fault on `addr`, a write if `write` is set: map the page on its first
+    /// access, growing the main stack down to it if needed, or resolve copy-on-write.
+    /// False if the access is not allowed.
+    pub fn handle_page_fault(&mut self, addr: VirtAddr, write: bool) -> bool {
+        use x86_64::structures::paging::mapper::TranslateResult;
+        let page = Page::containing_address(addr);
+        if !self.areas.iter().any(|area| area.contains(page)) && !self.grow_stack(page) {
+            return false;
+        }
+        let page_table = &mut self.page_table;
+        let area = self.areas.iter_mut().find(|area| area.contains(page)).unwrap();
+        match page_table.translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } => {
+                write && !flags.contains(PageTableFlags::WRITABLE) && area.resolve_copy_on_write(page, page_table)
+            }
+            _ => area.fault_in(page, write, page_table),
+        }
+    }
+
+    /// Extend the main stack down to `page` if it lies above the guard page and no other
+    /// area is in between.
+    fn grow_stack(&mut self, page: Page) -> bool {
+        if self.stack_top == 0 {
+            return false;
+        }
+        let top = Page::containing_address(VirtAddr::new(self.stack_top as u64));
+        let limit = Page::containing_address(VirtAddr::new((self.stack_top - USER_STACK_LIMIT) as u64));
+        let stack = match self.areas.iter().position(|area| area.contains(top)) {
+            Some(stack) => stack,
+            None => return false,
+        };
+        let start = self.areas[stack].page_range.start;
+        if page < limit || page >= start {
+            return false;
+        }
+        let blocked = self.areas.iter().any(|area| {
+            !area.page_range.is_empty() && area.page_range.start < start && page <= area.page_range.end
+        });
+        if blocked {
+            return false;
+        }
+        self.areas[stack].grow_down(page);
+        true
+    }
+    /// Kernel address of the byte at the user address `addr`, through the physical memory
+    /// offset. `addr` must lie in an area and be mapped user accessible, and writable if
+    /// `write` is set, faulting it in or resolving copy-on-write first.
+    pub fn translate_user(&mut self, addr: VirtAddr, write: bool) -> Option<*mut u8> {
+        use x86_64::structures::paging::mapper::TranslateResult;
+        let page = Page::containing_address(addr);
+        let usable = |memory_set: &Self| {
+            let flags = match memory_set.page_table.translate(addr) {
+                TranslateResult::Mapped { flags, .. } => flags,
+                _ => return false,
+            };
+            memory_set.areas.iter().any(|area| area.contains(page))
+                && flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                && (!write || flags.contains(PageTableFlags::WRITABLE))
+        };
+        if !usable(self) && !(self.handle_page_fault(addr, write) && usable(self)) {
+            return None;
+        }
+        let phys_addr = self.page_table.translate_addr(addr)?;
+        Some((phys_addr.as_u64() + physical_memory_offset()) as usize as *mut u8)
+    }
+
+    /// Map the segments of `elf`, those of its `interpreter` in the mmap range, an empty heap
+    /// after `elf` and the main stack.
+    pub fn read_elf(&mut self, elf: &ElfImage, interpreter: Option<&ElfImage>) -> ElfLayout {
+        let bias = if elf.position_independent {
+            PIE_BASE + random_offset(PIE_RANDOM_SIZE)
+        } else {
+            0
+        };
+        self.load_segments(elf, bias);
+        let elf_end = elf.segments.last().unwrap().end() + bias;
+
+        // The heap starts empty, `set_brk` grows it.
+        let heap_start = elf_end.align_up(PAGE_SIZE as u64) + random_offset(HEAP_RANDOM_SIZE);
+        let mut heap = MapArea::new(
+            heap_start,
+            heap_start,
+            PageTableFlags::PRESENT
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::NO_EXECUTE,
+        );
+        heap.resize(heap_start, &mut self.page_table);
+        self.areas.push(heap);
+        self.heap_start = heap_start.as_u64() as usize;
+        self.brk = self.heap_start;
+
+        let user_stack_top = VirtAddr::new((USER_STACK_TOP - random_offset(STACK_RANDOM_SIZE)) as u64);
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
+        self.stack_top = user_stack_top.as_u64() as usize;
+        self.reserve(
+            user_stack_bottom,
+            user_stack_top,
+            PageTableFlags::PRESENT
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::NO_EXECUTE,
         );
 
-        (
-            user_stack_top.as_u64() as usize,
-            elf.header.pt2.entry_point() as usize,
-        )
+        let interpreter_base = match interpreter {
+            Some(interpreter) => {
+                let base = MMAP_START + random_offset(PIE_RANDOM_SIZE);
+                self.load_segments(interpreter, base);
+                base
+            }
+            None => 0,
+        };
+        ElfLayout {
+            stack_top: user_stack_top.as_u64() as usize,
+            start: interpreter.map_or(bias + elf.entry_point, |interpreter| {
+                interpreter_base + interpreter.entry_point
+            }),
+            entry_point: bias + elf.entry_point,
+            program_headers: elf.program_headers.map_or(0, |addr| bias + addr),
+            program_header_count: elf.program_header_count,
+            interpreter_base,
+        }
     }
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+
+    /// Map the segments of `elf` moved up by `bias` and apply its relocations.
+    fn load_segments(&mut self, elf: &ElfImage, bias: usize) {
+        for segment in elf.segments.iter() {
+            self.map_segment(&segment.moved(bias), &elf.data[segment.file_range.clone()]);
+        }
+        for relocation in elf.relocations.iter() {
+            let value = bias.wrapping_add(relocation.addend);
+            self.write_bytes(VirtAddr::new((bias + relocation.offset) as u64), &value.to_le_bytes());
+        }
+    }
+
+    /// Write `data` at `addr` whatever the protection of its pages, which must lie in areas
+    /// and are mapped first if needed.
+    fn write_bytes(&mut self, addr: VirtAddr, data: &[u8]) {
+        let mut copied = 0;
+        while copied < data.len() {
+            let addr = addr + copied;
+            let page = Page::containing_address(addr);
+            let page_table = &mut self.page_table;
+            let area = self.areas.iter_mut().find(|area| area.contains(page)).unwrap();
+            area.map_one(page, page_table);
+            let len = (PAGE_SIZE - addr.as_u64() as usize % PAGE_SIZE).min(data.len() - copied);
+            copy_to_page(page_table, addr, &data[copied..copied + len]);
+            copied += len;
+        }
+    }
+    pub fn from_elf(elf: &ElfImage, interpreter: Option<&ElfImage>) -> (Self, ElfLayout) {
         let mut memory_set = Self::new();
-        let (user_stack_top, entry_point) = memory_set.read_elf(elf_data);
-        (memory_set, user_stack_top, entry_point)
+        let layout = memory_set.read_elf(elf, interpreter);
+        (memory_set, layout)
+    }
+
+    /// Map `segment` with `data` at its start. Its bytes past `data` are zeroed, as every
+    /// frame is when allocated. A page it shares with the previous segment becomes an area of
+    /// its own, allowing the accesses of both.
+    fn map_segment(&mut self, segment: &Segment, data: &[u8]) {
+        let first_page = Page::containing_address(segment.start);
+        let last_page = Page::containing_address(segment.end() - 1u64);
+        let mut start = segment.start;
+        let mut data = data;
+        if let Some(previous) = self.areas.iter().position(|area| area.contains(first_page)) {
+            if self.areas[previous].page_range.start < first_page {
+                let shared = self.areas[previous].split_off(first_page);
+                self.areas.push(shared);
+            }
+            let page_table = &mut self.page_table;
+            let shared = self.areas.iter_mut().find(|area| area.contains(first_page)).unwrap();
+            let mut flags = shared.flags | segment.flags;
+            if !(shared.flags & segment.flags).contains(PageTableFlags::NO_EXECUTE) {
+                flags -= PageTableFlags::NO_EXECUTE;
+            }
+            shared.map_one(first_page, page_table);
+            shared.set_flags(flags, page_table);
+            let next_page = (first_page + 1).start_address();
+            let len = data.len().min((next_page - start) as usize);
+            copy_to_page(page_table, start, &data[..len]);
+            if first_page == last_page {
+                return;
+            }
+            data = &data[len..];
+            start = next_page;
+        }
+        let mut area = MapArea::new(start, segment.end() - 1u64, segment.flags);
+        // The rest of the segment is zeroed as it is faulted in.
+        area.map_data(&mut self.page_table, data);
+        self.areas.push(area);
     }
 }
 
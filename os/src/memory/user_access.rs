@@ -0,0 +1,133 @@
+use crate::memory::memory_set::MemorySet;
+use crate::memory::{physical_memory_offset, PAGE_SIZE};
+use crate::process::current_process;
+use crate::system_call::error::SysError;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+
+/// A user pointer outside of the accessible user memory, reported as `EFAULT`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BadAddress;
+
+/// Longest string accepted from user space, including its NUL.
+pub const PATH_MAX: usize = 4096;
+
+/// Call `f` with the kernel view of every page-bounded chunk of the user range
+/// `addr..addr + len` of the current process, in order. Each page is checked just before
+/// its chunk, so the chunks before a bad page are already handled when it is reported.
+fn for_each_chunk(
+    addr: usize,
+    len: usize,
+    write: bool,
+    f: impl FnMut(*mut u8, usize),
+) -> Result<(), BadAddress> {
+    let process = current_process().unwrap();
+    let mut inner = process.inner_lock();
+    for_each_chunk_in(&mut inner.memory_set, addr, len, write, f)
+}
+
+fn for_each_chunk_in(
+    memory_set: &mut MemorySet,
+    addr: usize,
+    len: usize,
+    write: bool,
+    mut f: impl FnMut(*mut u8, usize),
+) -> Result<(), BadAddress> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = addr.checked_add(len).ok_or(BadAddress)?;
+    let mut start = addr;
+    while start < end {
+        let chunk_end = ((start / PAGE_SIZE + 1) * PAGE_SIZE).min(end);
+        let virt_addr = VirtAddr::try_new(start as u64).map_err(|_| BadAddress)?;
+        let kernel_ptr = memory_set.translate_user(virt_addr, write).ok_or(BadAddress)?;
+        f(kernel_ptr, chunk_end - start);
+        start = chunk_end;
+    }
+    Ok(())
+}
+
+/// Check that the user range can be read, or written if `write` is set.
+pub fn check_user_range(addr: usize, len: usize, write: bool) -> Result<(), BadAddress> {
+    for_each_chunk(addr, len, write, |_, _| {})
+}
+
+/// Physical address of the user address `addr`, which must be writable. Copy-on-write is
+/// resolved first, so the address is that of the page the process writes to.
+pub fn user_physical_address(addr: usize) -> Result<usize, BadAddress> {
+    let mut phys_addr = 0;
+    for_each_chunk(addr, 1, true, |ptr, _| {
+        phys_addr = ptr as usize - physical_memory_offset() as usize
+    })?;
+    Ok(phys_addr)
+}
+
+pub fn copy_from_user(dst: &mut [u8], src: *const u8) -> Result<(), BadAddress> {
+    let mut copied = 0;
+    for_each_chunk(src as usize, dst.len(), false, |ptr, len| {
+        let chunk = unsafe { core::slice::from_raw_parts(ptr, len) };
+        dst[copied..copied + len].copy_from_slice(chunk);
+        copied += len;
+    })
+}
+
+pub fn copy_to_user(dst: *mut u8, src: &[u8]) -> Result<(), BadAddress> {
+    let mut copied = 0;
+    for_each_chunk(dst as usize, src.len(), true, |ptr, len| {
+        let chunk = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+        chunk.copy_from_slice(&src[copied..copied + len]);
+        copied += len;
+    })
+}
+
+/// Copy into another address space than the current one, e.g. one being built by exec.
+pub fn copy_to_user_space(
+    memory_set: &mut MemorySet,
+    dst: usize,
+    src: &[u8],
+) -> Result<(), BadAddress> {
+    let mut copied = 0;
+    for_each_chunk_in(memory_set, dst, src.len(), true, |ptr, len| {
+        let chunk = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+        chunk.copy_from_slice(&src[copied..copied + len]);
+        copied += len;
+    })
+}
+
+pub fn read_from_user<T: Copy>(src: *const T) -> Result<T, BadAddress> {
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let dst = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, core::mem::size_of::<T>())
+    };
+    copy_from_user(dst, src as *const u8)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+pub fn write_to_user<T: Copy>(dst: *mut T, value: &T) -> Result<(), BadAddress> {
+    let src = unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(dst as *mut u8, src)
+}
+
+/// Read a NUL-terminated string of at most `PATH_MAX` bytes from user space.
+pub fn copy_str_from_user(src: *const u8) -> Result<String, SysError> {
+    let mut bytes = Vec::new();
+    let mut addr = src as usize;
+    while bytes.len() < PATH_MAX {
+        // Only look at the rest of the current page, the next one may not be mapped.
+        let len = (PAGE_SIZE - addr % PAGE_SIZE).min(PATH_MAX - bytes.len());
+        let mut chunk = vec![0u8; len];
+        copy_from_user(&mut chunk, addr as *const u8)?;
+        if let Some(nul) = chunk[..len].iter().position(|&byte| byte == 0) {
+            bytes.extend_from_slice(&chunk[..nul]);
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        bytes.extend_from_slice(&chunk[..len]);
+        addr += len;
+    }
+    Err(SysError::ENAMETOOLONG)
+}
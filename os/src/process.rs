@@ -1,41 +1,49 @@
+pub mod futex;
 pub mod kernel_stack;
 pub mod manager;
 pub mod pcb;
 pub mod pid;
+pub mod scheduler;
+pub mod signal;
 pub mod switch;
+pub mod task;
+pub mod wait_queue;
 
-use crate::loader::{get_app_data, get_app_data_by_name};
-use crate::process::manager::{add_process, fetch_process, ProcessManager};
-use crate::process::pcb::{ProcessControlBlock, ProcessStatus};
-use crate::process::switch::{switch_mm, switch_to};
-use alloc::collections::VecDeque;
+use crate::process::manager::{add_task, fetch_task};
+use crate::process::pcb::ProcessControlBlock;
+use crate::process::switch::switch_to;
+use crate::process::task::{TaskControlBlock, TaskStatus};
+use crate::process::wait_queue::{wake_task, WaitQueue};
+use alloc::string::String;
 use alloc::sync::Arc;
 use core::cell::RefCell;
 use lazy_static::lazy_static;
 use spin::Mutex;
-use x86_64::structures::paging::mapper::{TranslateResult, MappedFrame};
-use x86_64::structures::paging::{OffsetPageTable, PageTable, PageTableIndex, Translate, PhysFrame};
+use x86_64::structures::paging::{PageTable, PhysFrame, Translate};
 use x86_64::VirtAddr;
-use crate::system_call::TrapFrame;
 
 pub struct Processor {
     inner: RefCell<ProcessorInner>,
 }
 
 pub struct ProcessorInner {
-    current: Option<Arc<ProcessControlBlock>>,
+    current: Option<Arc<TaskControlBlock>>,
+    /// Last exited task, dropped once the processor no longer runs on its kernel stack.
+    exited: Option<Arc<TaskControlBlock>>,
     idle_process_context_ptr: usize,
-    idle_page_table: OffsetPageTable<'static>,
+    /// Page table the kernel booted with, used by kernel threads.
+    kernel_page_table: PhysFrame,
 }
 
 impl Processor {
     pub fn new() -> Self {
-        use crate::memory::current_offset_page_table;
+        use x86_64::registers::control::Cr3;
         Self {
             inner: RefCell::new(ProcessorInner {
                 current: None,
+                exited: None,
                 idle_process_context_ptr: 0,
-                idle_page_table: unsafe { current_offset_page_table() },
+                kernel_page_table: Cr3::read().0,
             }),
         }
     }
@@ -50,42 +58,70 @@ impl Processor {
     }
 
     pub fn run(&self) {
+        use crate::system_call::set_kernel_stack;
         use switch::switch_to;
+        use x86_64::instructions::interrupts;
         loop {
-            if let Some(process) = fetch_process() {
+            // Tasks may switch back here from an interrupt handler, and waking up from
+            // hlt must not race with checking for ready tasks.
+            interrupts::disable();
+            if let Some(task) = fetch_task() {
                 let idle_task_cx_ptr2 = self.get_idle_process_context_ptr2();
-                let mut process_inner = process.inner_lock();
-                let next_process_context = process_inner.process_context_ptr;
-
-                let page_table = {
-                    let page_table = &mut process_inner.memory_set.page_table;
-                    let page_table_virt =
-                        VirtAddr::new(page_table.level_4_table() as *mut PageTable as u64);
-                    let paget_table_phys= page_table.translate_addr(page_table_virt).unwrap();
-                    PhysFrame::containing_address(paget_table_phys)
+                let mut task_inner = task.inner_lock();
+                let next_task_context = task_inner.task_context_ptr;
+                task_inner.task_status = TaskStatus::Running;
+                drop(task_inner);
+
+                let page_table = match task.process() {
+                    Some(process) => {
+                        let page_table = &mut process.inner_lock().memory_set.page_table;
+                        let page_table_virt =
+                            VirtAddr::new(page_table.level_4_table() as *mut PageTable as u64);
+                        let paget_table_phys = page_table.translate_addr(page_table_virt).unwrap();
+                        PhysFrame::containing_address(paget_table_phys)
+                    }
+                    None => self.inner.borrow().kernel_page_table,
                 };
-                process_inner.process_status = ProcessStatus::Running;
-                drop(process_inner);
-                self.inner.borrow_mut().current = Some(process);
+                set_kernel_stack(task.kernel_stack.get_top());
+                self.inner.borrow_mut().current = Some(task);
 
                 unsafe {
                     use x86_64::registers::control::Cr3;
                     let (_, flags) = Cr3::read();
                     Cr3::write(page_table, flags);
-                    // switch_mm(page_table);
-                    switch_to(idle_task_cx_ptr2, next_process_context);
+                    switch_to(idle_task_cx_ptr2, next_task_context);
                 }
+                // Off the kernel stack of the task that ran, which may have exited.
+                let exited = self.inner.borrow_mut().exited.take();
+                drop(exited);
+            } else {
+                interrupts::enable_and_hlt();
             }
         }
     }
 
-    pub fn current(&self) -> Option<Arc<ProcessControlBlock>> {
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
         self.inner.borrow().current.as_ref().cloned()
     }
 
-    pub fn take_current(&self) -> Option<Arc<ProcessControlBlock>> {
+    pub fn take_current(&self) -> Option<Arc<TaskControlBlock>> {
         self.inner.borrow_mut().current.take()
     }
+
+    fn set_exited(&self, task: Arc<TaskControlBlock>) {
+        self.inner.borrow_mut().exited = Some(task);
+    }
+
+    /// Account one timer tick to the current task, true if it should be preempted.
+    pub fn tick(&self) -> bool {
+        use manager::TASK_MANAGER;
+        // The timer may fire while the kernel itself is using the processor.
+        let current = match self.inner.try_borrow() {
+            Ok(inner) => inner.current.as_ref().cloned(),
+            Err(_) => return false,
+        };
+        current.map_or(false, |current| TASK_MANAGER.lock().tick(&current))
+    }
 }
 
 unsafe impl Sync for Processor {}
@@ -95,52 +131,161 @@ lazy_static! {
 }
 
 lazy_static! {
-    pub static ref INITPROC: Arc<ProcessControlBlock> = Arc::new(ProcessControlBlock::new(
-        get_app_data_by_name("initproc").unwrap()
-    ));
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        use crate::memory::elf::ElfImage;
+        let elf_data = crate::fs::read_file("/bin/initproc").unwrap();
+        let elf = ElfImage::parse(&elf_data).expect("Invalid initproc ELF.");
+        let interpreter_data = elf
+            .interpreter
+            .map(|path| crate::fs::read_file(path).expect("Cannot read the initproc interpreter."));
+        let interpreter = interpreter_data
+            .as_deref()
+            .map(|data| ElfImage::parse_interpreter(data).expect("Invalid initproc interpreter."));
+        ProcessControlBlock::new(&elf, interpreter.as_ref(), &[String::from("/bin/initproc")])
+    };
 }
 
 pub fn add_initproc() {
-    use manager::add_process;
-    add_process(INITPROC.clone());
+    let task = INITPROC.inner_lock().tasks[0].clone();
+    add_task(task);
+}
+
+/// Start a kernel thread running `entry`, scheduled along with the user tasks.
+pub fn spawn_kernel_thread(entry: fn()) {
+    add_task(TaskControlBlock::new_kernel(entry));
+}
+
+/// Process `pid`, every process descends from initproc until it is released.
+pub fn find_process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    let mut processes = alloc::vec![INITPROC.clone()];
+    while let Some(process) = processes.pop() {
+        if process.getpid() == pid {
+            return Some(process);
+        }
+        processes.extend(process.inner_lock().children.iter().cloned());
+    }
+    None
 }
 
 pub fn run_processes() {
     PROCESSOR.run()
 }
 
-pub fn take_current_process() -> Option<Arc<ProcessControlBlock>> {
+pub fn timer_tick() -> bool {
+    PROCESSOR.tick()
+}
+
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSOR.take_current()
 }
-pub fn current_process() -> Option<Arc<ProcessControlBlock>> {
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSOR.current()
 }
+/// Process of the current task, None in kernel threads.
+pub fn current_process() -> Option<Arc<ProcessControlBlock>> {
+    current_task().and_then(|task| task.process())
+}
 
 pub fn suspend_current_and_run_next() {
-    let process = take_current_process().unwrap();
-    let mut inner = process.inner_lock();
-    let task_context_ptr2 = inner.get_process_context_ptr2();
-    inner.process_status = ProcessStatus::Ready;
+    let task = take_current_task().unwrap();
+    let mut inner = task.inner_lock();
+    let task_context_ptr2 = inner.get_task_context_ptr2();
+    inner.task_status = TaskStatus::Ready;
     drop(inner);
-    add_process(process);
+    add_task(task);
     schedule(task_context_ptr2);
 }
 
+/// Put the current task to sleep on `wait_queue` until it is woken up.
+///
+/// Interrupts must stay disabled from checking the wait condition until this call
+/// if the condition can be changed by an interrupt handler.
+pub fn block_current_and_run_next(wait_queue: &Mutex<WaitQueue>) {
+    use manager::TASK_MANAGER;
+    use x86_64::instructions::interrupts::without_interrupts;
+    let task = take_current_task().unwrap();
+    let mut inner = task.inner_lock();
+    let task_context_ptr2 = inner.get_task_context_ptr2();
+    inner.task_status = TaskStatus::Blocked;
+    drop(inner);
+    TASK_MANAGER.lock().block(&task);
+    wait_queue.lock().push(task.clone());
+    schedule(task_context_ptr2);
+    // Still queued if woken up by a signal, the queue must not keep the task alive.
+    without_interrupts(|| wait_queue.lock().remove(&task));
+}
+
+/// Make `task` exit the next time it would return to user mode.
+pub fn kill_task(task: &Arc<TaskControlBlock>) {
+    let mut inner = task.inner_lock();
+    inner.killed = true;
+    if inner.task_status == TaskStatus::Blocked {
+        drop(inner);
+        wake_task(task.clone());
+    }
+}
+
+/// Exit the whole process of the current task with `exit_code`, its other tasks are
+/// killed and the last one to exit releases the process.
 pub fn exit_current_and_run_next(exit_code: isize) {
-    let process = take_current_process().unwrap();
-    let mut inner = process.inner_lock();
-    inner.process_status = ProcessStatus::Zombie;
-    inner.exit_code = exit_code;
-    {
-        let mut initproc_inner = INITPROC.inner_lock();
-        for child in inner.children.iter() {
-            child.inner_lock().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+    let task = current_task().unwrap();
+    if let Some(process) = task.process() {
+        let mut inner = process.inner_lock();
+        if !inner.exiting {
+            inner.exiting = true;
+            inner.exit_code = exit_code;
         }
+        inner
+            .tasks
+            .iter()
+            .filter(|other| !Arc::ptr_eq(other, &task))
+            .for_each(kill_task);
     }
-    inner.children.clear();
-    drop(inner);
-    drop(process);
+    drop(task);
+    exit_current_task(exit_code);
+}
+
+/// Exit the current task only, the process exits with `exit_code` if it was its last task.
+pub fn exit_current_task(exit_code: isize) {
+    use crate::process::pcb::thread_stack_range;
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_lock();
+    task_inner.task_status = TaskStatus::Zombie;
+    task_inner.exit_code = exit_code;
+    let user_stack_slot = task_inner.user_stack_slot.take();
+    drop(task_inner);
+    task.join_queue.lock().wake_all();
+
+    if let Some(process) = task.process() {
+        let mut inner = process.inner_lock();
+        if let Some(slot) = user_stack_slot {
+            let (bottom, _) = thread_stack_range(slot);
+            inner.memory_set.remove_area_with_start_addr(bottom);
+        }
+        if inner.tasks.iter().all(|task| task.inner_lock().is_zombie()) {
+            if !inner.exiting {
+                inner.exit_code = exit_code;
+            }
+            {
+                let mut initproc_inner = INITPROC.inner_lock();
+                for child in inner.children.iter() {
+                    child.inner_lock().parent = Some(Arc::downgrade(&INITPROC));
+                    initproc_inner.children.push(child.clone());
+                }
+            }
+            if !inner.children.is_empty() {
+                INITPROC.wait_queue.lock().wake_all();
+            }
+            inner.children.clear();
+            if let Some(parent) = inner.parent.as_ref().and_then(|parent| parent.upgrade()) {
+                parent.wait_queue.lock().wake_all();
+            }
+            inner.memory_set.remove_all_areas();
+            inner.fd_table.clear();
+            inner.zombie = true;
+        }
+    }
+    PROCESSOR.set_exited(task);
     let _unused: usize = 0;
     schedule(&_unused as *const _);
 }
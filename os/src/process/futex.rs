@@ -0,0 +1,51 @@
+use crate::process::wait_queue::WaitQueue;
+use crate::process::block_current_and_run_next;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+lazy_static! {
+    /// Tasks waiting on a futex, by the physical address of the futex word so that every
+    /// mapping of it shares the queue. Empty queues are removed.
+    static ref FUTEX_QUEUES: Mutex<BTreeMap<usize, Arc<Mutex<WaitQueue>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Block the current task on the futex at `phys_addr` until `futex_wake` or a signal
+/// wakes it up.
+pub fn futex_wait(phys_addr: usize) {
+    let queue = FUTEX_QUEUES
+        .lock()
+        .entry(phys_addr)
+        .or_insert_with(|| Arc::new(Mutex::new(WaitQueue::new())))
+        .clone();
+    block_current_and_run_next(&queue);
+    let mut queues = FUTEX_QUEUES.lock();
+    let queue_inner = queue.lock();
+    if queue_inner.is_empty() && queues.get(&phys_addr).map_or(false, |q| Arc::ptr_eq(q, &queue)) {
+        queues.remove(&phys_addr);
+    }
+}
+
+/// Wake up at most `count` tasks waiting on the futex at `phys_addr`, returning how many.
+pub fn futex_wake(phys_addr: usize, count: usize) -> usize {
+    let mut queues = FUTEX_QUEUES.lock();
+    let queue = match queues.get(&phys_addr) {
+        Some(queue) => queue.clone(),
+        None => return 0,
+    };
+    let mut queue_inner = queue.lock();
+    let mut woken = 0;
+    while woken < count && !queue_inner.is_empty() {
+        queue_inner.wake_one();
+        woken += 1;
+    }
+    if queue_inner.is_empty() {
+        queues.remove(&phys_addr);
+    }
+    woken
+}
@@ -1,11 +1,11 @@
 use crate::memory::memory_set::KERNEL_SPACE;
-use crate::process::pid::PidHandle;
 use x86_64::structures::paging::PageTableFlags;
 use x86_64::VirtAddr;
 
+/// Kernel stack of a task, picked by its tid and mapped in the kernel half while it lives.
 #[derive(Debug, Clone)]
 pub struct KernelStack {
-    pid: usize,
+    tid: usize,
 }
 
 fn kernel_stack_address(app_id: usize) -> (u64, u64) {
@@ -16,20 +16,18 @@ fn kernel_stack_address(app_id: usize) -> (u64, u64) {
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
-        let pid = pid_handle.0;
-
-        let (bottom, top) = kernel_stack_address(pid);
+    pub fn new(tid: usize) -> Self {
+        let (bottom, top) = kernel_stack_address(tid);
         KERNEL_SPACE.lock().insert(
             VirtAddr::new(bottom),
             VirtAddr::new(top),
             PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
             None,
         );
-        Self { pid }
+        Self { tid }
     }
     pub fn get_top(&self) -> usize {
-        let (_, top) = kernel_stack_address(self.pid);
+        let (_, top) = kernel_stack_address(self.tid);
         top as usize
     }
 
@@ -48,7 +46,7 @@ impl KernelStack {
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        let (bottom, _) = kernel_stack_address(self.pid);
+        let (bottom, _) = kernel_stack_address(self.tid);
         KERNEL_SPACE
             .lock()
             .remove_area_with_start_addr(VirtAddr::new(bottom));
@@ -1,34 +1,55 @@
-use crate::process::pcb::ProcessControlBlock;
-use alloc::collections::VecDeque;
+use crate::process::task::TaskControlBlock;
+use crate::process::scheduler::{Scheduler, SelectedScheduler};
 use alloc::sync::Arc;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-pub struct ProcessManager {
-    ready_queue: VecDeque<Arc<ProcessControlBlock>>,
+pub struct TaskManager {
+    scheduler: SelectedScheduler,
 }
 
-impl ProcessManager {
+impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
+            scheduler: SelectedScheduler::new(),
         }
     }
-    pub fn add(&mut self, process: Arc<ProcessControlBlock>) {
-        self.ready_queue.push_back(process);
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.add(task);
     }
-    pub fn fetch(&mut self) -> Option<Arc<ProcessControlBlock>> {
-        self.ready_queue.pop_front()
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.scheduler.fetch()
+    }
+    pub fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.scheduler.on_tick(current)
+    }
+    pub fn block(&mut self, task: &Arc<TaskControlBlock>) {
+        self.scheduler.on_block(task);
+    }
+    pub fn wake(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.on_wake(task);
     }
 }
 
 lazy_static! {
-    pub static ref PROCESS_MANAGER: Mutex<ProcessManager> = Mutex::new(ProcessManager::new());
+    pub static ref TASK_MANAGER: Mutex<TaskManager> = Mutex::new(TaskManager::new());
 }
 
-pub fn add_process(process: Arc<ProcessControlBlock>) {
-    PROCESS_MANAGER.lock().add(process);
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().add(task);
 }
-pub fn fetch_process() -> Option<Arc<ProcessControlBlock>> {
-    PROCESS_MANAGER.lock().fetch()
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    use crate::process::task::TaskStatus;
+    use crate::process::wait_queue::take_woken_tasks;
+    let mut manager = TASK_MANAGER.lock();
+    for task in take_woken_tasks() {
+        // A task woken up by a signal may be woken up again by the queue it was blocked on.
+        let mut inner = task.inner_lock();
+        if inner.task_status == TaskStatus::Blocked {
+            inner.task_status = TaskStatus::Ready;
+            drop(inner);
+            manager.wake(task);
+        }
+    }
+    manager.fetch()
 }
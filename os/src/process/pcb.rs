@@ -1,62 +1,55 @@
-use super::{kernel_stack::KernelStack, pid::PidHandle};
-use crate::memory::memory_set::MemorySet;
+use super::pid::PidHandle;
+use crate::fs::stdio::{Stdin, Stdout};
+use crate::fs::File;
+use crate::memory::elf::ElfImage;
+use crate::memory::memory_set::{ElfLayout, MemorySet};
 use crate::process::pid::alloc_pid;
-use crate::process::ProcessorInner;
+use crate::process::scheduler::SchedulingInfo;
+use crate::process::signal::SignalState;
+use crate::process::task::TaskControlBlock;
+use crate::process::wait_queue::WaitQueue;
 use crate::system_call::TrapFrame;
 use alloc::{
+    string::String,
     sync::{Arc, Weak},
+    vec,
     vec::Vec,
 };
 use spin::{Mutex, MutexGuard};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum ProcessStatus {
-    Ready,
-    Running,
-    Zombie,
-}
+/// Number of descriptors a process can have open.
+pub const MAX_FD: usize = 1024;
 
-#[repr(C)]
-pub struct ProcessContext {
-    r15: usize,
-    r14: usize,
-    r13: usize,
-    r12: usize,
-    r11: usize,
-    rbx: usize,
-    rbp: usize,
-    rip: usize,
-}
-
-impl ProcessContext {
-    pub fn return_from_trap() -> Self {
-        use crate::system_call::trap_ret;
-        Self {
-            r15: 0,
-            r14: 0,
-            r13: 0,
-            r12: 0,
-            r11: 0,
-            rbx: 0,
-            rbp: 0,
-            rip: trap_ret as usize,
-        }
-    }
-}
+/// Top of the user stack of the first thread created by `thread_create`, the stacks of
+/// the next ones follow downwards.
+pub const THREAD_STACK_TOP: usize = 0x40_0000_0000;
+pub const THREAD_STACK_SIZE: usize = 256 * 1024;
 
+/// Resources shared by the tasks of a process.
 pub struct ProcessControlBlock {
     pub pid: PidHandle,
-    pub kernel_stack: KernelStack,
+    /// Parent waiting for this process' children to exit.
+    pub wait_queue: Mutex<WaitQueue>,
     inner: Mutex<ProcessControlBlockInner>,
 }
 
 pub struct ProcessControlBlockInner {
     pub memory_set: MemorySet,
-    pub process_status: ProcessStatus,
-    pub process_context_ptr: usize,
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
     pub exit_code: isize,
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+    /// Absolute path of the working directory, without `.` or `..`.
+    pub cwd: String,
+    pub signals: SignalState,
+    /// Tasks not joined yet, exited ones included.
+    pub tasks: Vec<Arc<TaskControlBlock>>,
+    /// Set by `exit`, whose exit code is kept while the other tasks exit.
+    pub exiting: bool,
+    /// Set once every task has exited, until the parent releases the process.
+    pub zombie: bool,
 }
 
 impl ProcessControlBlock {
@@ -66,86 +59,236 @@ impl ProcessControlBlock {
     pub fn getpid(&self) -> usize {
         self.pid.0
     }
-    pub fn get_trap_frame(&self) -> &'static mut TrapFrame {
-        unsafe {
-            &mut *((self.kernel_stack.get_top() - core::mem::size_of::<TrapFrame>())
-                as *mut TrapFrame)
-        }
-    }
-    pub fn new(elf_data: &[u8]) -> Self {
-        let (memory_set, user_stack, entry_point) = MemorySet::from_elf(elf_data);
-        let pid = alloc_pid();
-        let kernel_stack = KernelStack::new(&pid);
-        // Push trap frame
-        let mut trap_frame = TrapFrame::new();
-        trap_frame.rsp = user_stack as u64; // User stack
-        trap_frame.rcx = entry_point as u64; // Return address from syscall
-        trap_frame.r11 = 0x203; // RFlags
-        kernel_stack.push_to_top(trap_frame, 0);
-        // Push process context
-        let process_context_ptr = kernel_stack.push_to_top(
-            ProcessContext::return_from_trap(),
-            core::mem::size_of::<TrapFrame>(),
-        );
-        let task_control_block = Self {
-            pid,
-            kernel_stack,
+    fn with_memory_set(
+        memory_set: MemorySet,
+        parent: Option<Weak<ProcessControlBlock>>,
+        fd_table: Vec<Option<Arc<dyn File>>>,
+        cwd: String,
+        signals: SignalState,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            pid: alloc_pid(),
+            wait_queue: Mutex::new(WaitQueue::new()),
             inner: Mutex::new(ProcessControlBlockInner {
                 memory_set,
-                process_status: ProcessStatus::Ready,
-                process_context_ptr: process_context_ptr as usize,
-                parent: None,
+                parent,
                 children: Vec::new(),
                 exit_code: 0,
+                fd_table,
+                cwd,
+                signals,
+                tasks: Vec::new(),
+                exiting: false,
+                zombie: false,
             }),
-        };
-        task_control_block
+        })
     }
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// A process running `elf`, through its `interpreter` if it has one, with the arguments
+    /// `args`. Its first task is not added to the scheduler yet.
+    pub fn new(elf: &ElfImage, interpreter: Option<&ElfImage>, args: &[String]) -> Arc<Self> {
+        let (mut memory_set, layout) = MemorySet::from_elf(elf, interpreter);
+        let user_stack = push_arguments(&mut memory_set, &layout, args, &[]);
+        let fd_table: Vec<Option<Arc<dyn File>>> = vec![
+            // 0 -> stdin
+            Some(Arc::new(Stdin)),
+            // 1 -> stdout
+            Some(Arc::new(Stdout)),
+            // 2 -> stderr
+            Some(Arc::new(Stdout)),
+        ];
+        let process = Self::with_memory_set(
+            memory_set,
+            None,
+            fd_table,
+            String::from("/"),
+            SignalState::new(),
+        );
+        let task = TaskControlBlock::new_user(
+            &process,
+            None,
+            TrapFrame::new_user(layout.start, user_stack),
+            SchedulingInfo::new(),
+        );
+        process.inner_lock().tasks.push(task);
+        process
+    }
+    /// Replace the program of the process, `task` being its only task left.
+    pub fn exec(
+        &self,
+        task: &Arc<TaskControlBlock>,
+        elf: &ElfImage,
+        interpreter: Option<&ElfImage>,
+        args: &[String],
+        envs: &[String],
+    ) {
         let mut inner = self.inner_lock();
+        inner.tasks.retain(|other| Arc::ptr_eq(other, task));
         inner.memory_set.remove_all_areas();
-        let (user_stack, entry_point) = inner.memory_set.read_elf(elf_data);
-        let trap_frame = self.get_trap_frame();
-        trap_frame.rsp = user_stack as u64; // User stack
-        trap_frame.rcx = entry_point as u64; // Return address from syscall
-        trap_frame.r11 = 0x203; // RFlags
+        let layout = inner.memory_set.read_elf(elf, interpreter);
+        inner.signals.exec();
+        let user_stack = push_arguments(&mut inner.memory_set, &layout, args, envs);
+        task.inner_lock().user_stack_slot = None;
+        *task.get_trap_frame() = TrapFrame::new_user(layout.start, user_stack);
     }
-    pub fn fork(self: &Arc<ProcessControlBlock>) -> Arc<ProcessControlBlock> {
-        use crate::println;
+    /// A copy of the process with a copy of `task` as its only task, which is not added to
+    /// the scheduler yet. None if memory runs out.
+    pub fn fork(self: &Arc<Self>, task: &Arc<TaskControlBlock>) -> Option<Arc<Self>> {
         let mut parent_inner = self.inner_lock();
-        let memory_set = MemorySet::from(&parent_inner.memory_set);
-        let pid = alloc_pid();
-        let kernel_stack = KernelStack::new(&pid);
-        let trap_frame_size = core::mem::size_of::<TrapFrame>();
-        let process_context_ptr =
-            kernel_stack.push_to_top(ProcessContext::return_from_trap(), trap_frame_size);
-        let parent_trap_frame = self.get_trap_frame();
-        kernel_stack.push_to_top(parent_trap_frame.clone(), 0);
-        let process_control_block = Arc::new(ProcessControlBlock {
-            pid,
-            kernel_stack,
-            inner: Mutex::new(ProcessControlBlockInner {
-                memory_set,
-                process_status: ProcessStatus::Ready,
-                process_context_ptr: process_context_ptr as usize,
-                parent: Some(Arc::downgrade(self)),
-                children: Vec::new(),
-                exit_code: 0,
-            }),
-        });
-        parent_inner.children.push(process_control_block.clone());
-        process_control_block
+        let memory_set = MemorySet::from(&mut parent_inner.memory_set)?;
+        let child = Self::with_memory_set(
+            memory_set,
+            Some(Arc::downgrade(self)),
+            parent_inner.fd_table.clone(),
+            parent_inner.cwd.clone(),
+            parent_inner.signals.fork(),
+        );
+        let (scheduling, user_stack_slot) = {
+            let task_inner = task.inner_lock();
+            (task_inner.scheduling.clone(), task_inner.user_stack_slot)
+        };
+        let child_task =
+            TaskControlBlock::new_user(&child, None, *task.get_trap_frame(), scheduling);
+        child_task.inner_lock().user_stack_slot = user_stack_slot;
+        let mut child_inner = child.inner_lock();
+        // Only the forking task is copied, the user stacks of the others are not used.
+        for other in parent_inner.tasks.iter().filter(|other| !Arc::ptr_eq(other, task)) {
+            if let Some(slot) = other.inner_lock().user_stack_slot {
+                let (bottom, _) = thread_stack_range(slot);
+                child_inner.memory_set.remove_area_with_start_addr(bottom);
+            }
+        }
+        child_inner.tasks.push(child_task);
+        drop(child_inner);
+        parent_inner.children.push(child.clone());
+        Some(child)
     }
+    /// Create a task starting at `entry` with `args` in rdi and rsi on a new user stack,
+    /// returning it without adding it to the scheduler. None if no stack slot is left clear
+    /// of the other mappings.
+    pub fn create_thread(
+        self: &Arc<Self>,
+        entry: usize,
+        args: [usize; 2],
+        scheduling: SchedulingInfo,
+    ) -> Option<Arc<TaskControlBlock>> {
+        use crate::memory::memory_set::MMAP_END;
+        use crate::memory::PAGE_SIZE;
+        let mut inner = self.inner_lock();
+        let slots = (THREAD_STACK_TOP - MMAP_END) / (THREAD_STACK_SIZE + PAGE_SIZE);
+        let slot = (0..slots).find(|&slot| {
+            // The stack and its guard page must not overlap mmap regions or the heap.
+            let guard = thread_stack_range(slot).0 - PAGE_SIZE as u64;
+            inner
+                .tasks
+                .iter()
+                .all(|task| task.inner_lock().user_stack_slot != Some(slot))
+                && inner
+                    .memory_set
+                    .find_free_range(THREAD_STACK_SIZE + PAGE_SIZE, Some(guard))
+                    == Some(guard)
+        })?;
+        let (bottom, top) = thread_stack_range(slot);
+        inner.memory_set.reserve(
+            bottom,
+            top,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
+        );
+        // Entered as if called, with a null return address.
+        let mut trap_frame = TrapFrame::new_user(entry, top.as_u64() as usize - 8);
+        trap_frame.rdi = args[0] as u64;
+        trap_frame.rsi = args[1] as u64;
+        let task = TaskControlBlock::new_user(self, Some(alloc_pid()), trap_frame, scheduling);
+        task.inner_lock().user_stack_slot = Some(slot);
+        inner.tasks.push(task.clone());
+        Some(task)
+    }
+}
+
+/// User stack of the thread in `slot`, with a guard page below.
+pub fn thread_stack_range(slot: usize) -> (VirtAddr, VirtAddr) {
+    use crate::memory::PAGE_SIZE;
+    let top = THREAD_STACK_TOP - slot * (THREAD_STACK_SIZE + PAGE_SIZE);
+    (VirtAddr::new((top - THREAD_STACK_SIZE) as u64), VirtAddr::new(top as u64))
+}
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_BASE: usize = 7;
+const AT_ENTRY: usize = 9;
+
+/// Lay out `args` and `envs` below the top of the stack as the System V x86-64 ABI expects at
+/// process entry: argc, the argv and envp pointer arrays and the auxiliary vector describing
+/// `layout`, then the strings. Returns the initial stack pointer, which points to argc and is
+/// 16-byte aligned.
+fn push_arguments(
+    memory_set: &mut MemorySet,
+    layout: &ElfLayout,
+    args: &[String],
+    envs: &[String],
+) -> usize {
+    use crate::memory::elf::PROGRAM_HEADER_SIZE;
+    use crate::memory::user_access::copy_to_user_space;
+    use crate::memory::PAGE_SIZE;
+    let mut sp = layout.stack_top;
+    let mut push_strings = |strings: &[String]| -> Vec<usize> {
+        let mut pointers = Vec::new();
+        for string in strings.iter() {
+            sp -= string.len() + 1;
+            let mut bytes = Vec::from(string.as_bytes());
+            bytes.push(0);
+            copy_to_user_space(memory_set, sp, &bytes).expect("User stack is too small.");
+            pointers.push(sp);
+        }
+        pointers
+    };
+    let env_pointers = push_strings(envs);
+    let arg_pointers = push_strings(args);
+    sp &= !0xf;
+
+    let mut words = vec![args.len()];
+    words.extend(arg_pointers);
+    words.push(0);
+    words.extend(env_pointers);
+    words.push(0);
+    words.extend(&[
+        AT_PHDR, layout.program_headers,
+        AT_PHENT, PROGRAM_HEADER_SIZE,
+        AT_PHNUM, layout.program_header_count,
+        AT_PAGESZ, PAGE_SIZE,
+        AT_BASE, layout.interpreter_base,
+        AT_ENTRY, layout.entry_point,
+        AT_NULL, 0,
+    ]);
+    if words.len() % 2 == 1 {
+        words.push(0);
+    }
+    sp -= words.len() * core::mem::size_of::<usize>();
+    let bytes = unsafe {
+        core::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8)
+    };
+    copy_to_user_space(memory_set, sp, bytes).expect("User stack is too small.");
+    sp
 }
 
 impl ProcessControlBlockInner {
-    pub fn process_status(&self) -> ProcessStatus {
-        self.process_status
+    pub fn is_zombie(&self) -> bool {
+        self.zombie
     }
-    pub fn get_process_context_ptr2(&self) -> *const usize {
-        &self.process_context_ptr as *const usize
+    /// Lowest free descriptor, None once `MAX_FD` descriptors are open.
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        if let Some(fd) = self.fd_table.iter().position(|file| file.is_none()) {
+            Some(fd)
+        } else if self.fd_table.len() < MAX_FD {
+            self.fd_table.push(None);
+            Some(self.fd_table.len() - 1)
+        } else {
+            None
+        }
     }
-    pub fn is_zombie(&self) -> bool {
-        self.process_status == ProcessStatus::Zombie
+    pub fn get_file(&self, fd: usize) -> Option<Arc<dyn File>> {
+        self.fd_table.get(fd).cloned().flatten()
     }
 }
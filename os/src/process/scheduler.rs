@@ -0,0 +1,76 @@
+pub mod fifo;
+pub mod mlfq;
+pub mod round_robin;
+pub mod stride;
+
+use crate::process::task::TaskControlBlock;
+use alloc::sync::Arc;
+
+/// Timer ticks a task may run in user mode before it is preempted.
+pub const TIME_SLICE: usize = 5;
+pub const DEFAULT_PRIORITY: usize = 16;
+pub const MIN_PRIORITY: usize = 1;
+pub const MAX_PRIORITY: usize = 64;
+
+/// Per task state kept for the scheduler in `TaskControlBlockInner`.
+#[derive(Debug, Clone)]
+pub struct SchedulingInfo {
+    pub priority: usize,
+    /// Ticks used in the current time slice.
+    pub ticks: usize,
+    /// Virtual time of the stride scheduler.
+    pub pass: usize,
+    /// Queue level of the multi-level feedback queue.
+    pub level: usize,
+}
+
+impl SchedulingInfo {
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            ticks: 0,
+            pass: 0,
+            level: 0,
+        }
+    }
+}
+
+/// A scheduling policy deciding which ready task runs next.
+pub trait Scheduler {
+    /// Put a ready task into the policy's queues.
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Take the next task to run.
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Called on each timer tick the current task spends in user mode,
+    /// true if it should be preempted.
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool;
+    /// Called when the current task stops being runnable until it is woken up.
+    fn on_block(&mut self, _task: &Arc<TaskControlBlock>) {}
+    /// Called when a blocked task becomes ready again.
+    fn on_wake(&mut self, task: Arc<TaskControlBlock>) {
+        self.add(task)
+    }
+}
+
+/// Count `current` a tick of its slice, true once `slice` ticks are used up.
+fn slice_expired(current: &Arc<TaskControlBlock>, slice: usize) -> bool {
+    let mut inner = current.inner_lock();
+    inner.scheduling.ticks += 1;
+    inner.scheduling.ticks >= slice
+}
+
+#[cfg(feature = "sched-stride")]
+pub type SelectedScheduler = stride::StrideScheduler;
+#[cfg(all(feature = "sched-mlfq", not(feature = "sched-stride")))]
+pub type SelectedScheduler = mlfq::MlfqScheduler;
+#[cfg(all(
+    feature = "sched-fifo",
+    not(any(feature = "sched-stride", feature = "sched-mlfq"))
+))]
+pub type SelectedScheduler = fifo::FifoScheduler;
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-mlfq",
+    feature = "sched-fifo"
+)))]
+pub type SelectedScheduler = round_robin::RoundRobinScheduler;
@@ -0,0 +1,74 @@
+use super::{Scheduler, TIME_SLICE};
+use crate::process::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+const LEVELS: usize = 3;
+/// Ticks after which every task is moved back to the top level.
+const BOOST_INTERVAL: usize = 200;
+
+/// Multi-level feedback queue.
+///
+/// Tasks start at level 0. Using up the whole time slice of a level, which doubles
+/// on every level, moves a task one level down, while tasks that block keep
+/// their level. All tasks are boosted back to level 0 periodically.
+pub struct MlfqScheduler {
+    ready_queues: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    ticks: usize,
+}
+
+impl MlfqScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready_queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            ticks: 0,
+        }
+    }
+
+    fn time_slice(level: usize) -> usize {
+        TIME_SLICE << level
+    }
+
+    fn boost(&mut self) {
+        for level in 1..LEVELS {
+            while let Some(task) = self.ready_queues[level].pop_front() {
+                task.inner_lock().scheduling.level = 0;
+                self.ready_queues[0].push_back(task);
+            }
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let level = {
+            let mut inner = task.inner_lock();
+            inner.scheduling.ticks = 0;
+            inner.scheduling.level
+        };
+        self.ready_queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queues
+            .iter_mut()
+            .find_map(|ready_queue| ready_queue.pop_front())
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks % BOOST_INTERVAL == 0 {
+            self.boost();
+            current.inner_lock().scheduling.level = 0;
+        }
+        let mut inner = current.inner_lock();
+        let scheduling = &mut inner.scheduling;
+        scheduling.ticks += 1;
+        if scheduling.ticks >= Self::time_slice(scheduling.level) {
+            scheduling.level = (scheduling.level + 1).min(LEVELS - 1);
+            return true;
+        }
+        let level = scheduling.level;
+        self.ready_queues[..level]
+            .iter()
+            .any(|ready_queue| !ready_queue.is_empty())
+    }
+}
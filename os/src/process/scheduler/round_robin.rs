@@ -0,0 +1,30 @@
+use super::{slice_expired, Scheduler, TIME_SLICE};
+use crate::process::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// FIFO queue where each task is preempted after `TIME_SLICE` ticks.
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RoundRobinScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        task.inner_lock().scheduling.ticks = 0;
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        slice_expired(current, TIME_SLICE)
+    }
+}
@@ -0,0 +1,75 @@
+use super::{slice_expired, Scheduler, TIME_SLICE};
+use crate::process::task::TaskControlBlock;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+const BIG_STRIDE: usize = 0x10000;
+
+/// Stride scheduling, a task gets CPU time in proportion to its priority.
+///
+/// The task with the smallest pass runs next and its pass grows by
+/// `BIG_STRIDE / priority` each time it is picked.
+pub struct StrideScheduler {
+    ready_queue: BinaryHeap<StrideEntry>,
+}
+
+struct StrideEntry {
+    pass: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for StrideEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.pass == other.pass
+    }
+}
+
+impl Eq for StrideEntry {}
+
+impl PartialOrd for StrideEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StrideEntry {
+    // BinaryHeap is a max heap, the smallest pass must compare greatest.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.pass.cmp(&self.pass)
+    }
+}
+
+impl StrideScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready_queue: BinaryHeap::new(),
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let pass = {
+            let mut inner = task.inner_lock();
+            inner.scheduling.ticks = 0;
+            // A task coming back after a long sleep must not monopolize the CPU.
+            if let Some(min) = self.ready_queue.peek() {
+                inner.scheduling.pass = inner.scheduling.pass.max(min.pass);
+            }
+            inner.scheduling.pass
+        };
+        self.ready_queue.push(StrideEntry { pass, task });
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let StrideEntry { task, .. } = self.ready_queue.pop()?;
+        {
+            let mut inner = task.inner_lock();
+            inner.scheduling.pass += BIG_STRIDE / inner.scheduling.priority;
+        }
+        Some(task)
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        slice_expired(current, TIME_SLICE)
+    }
+}
@@ -0,0 +1,397 @@
+use crate::memory::user_access::{read_from_user, write_to_user, BadAddress};
+use crate::println;
+use crate::process::pcb::ProcessControlBlock;
+use crate::process::task::TaskStatus;
+use crate::process::wait_queue::{wake_task, WaitQueue};
+use crate::process::{
+    block_current_and_run_next, current_process, current_task, exit_current_and_run_next,
+    exit_current_task,
+};
+use crate::system_call::TrapFrame;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Signals are numbered from 1 to `NSIG - 1` as on Linux.
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+/// Handlers with a special meaning.
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// Do not block the signal while its handler runs.
+pub const SA_NODEFER: usize = 0x4000_0000;
+/// Reset the handler to `SIG_DFL` once the signal is delivered.
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+/// How `sigprocmask` changes the blocked signals.
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Bytes below the user stack pointer the x86-64 ABI lets functions use without moving it.
+const RED_ZONE_SIZE: usize = 128;
+
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct SignalSet(pub u64);
+
+impl SignalSet {
+    const fn bit(signal: usize) -> u64 {
+        1 << (signal - 1)
+    }
+    pub fn contains(self, signal: usize) -> bool {
+        self.0 & Self::bit(signal) != 0
+    }
+    pub fn insert(&mut self, signal: usize) {
+        self.0 |= Self::bit(signal);
+    }
+    pub fn remove(&mut self, signal: usize) {
+        self.0 &= !Self::bit(signal);
+    }
+    /// Lowest signal in the set.
+    fn first(self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize + 1)
+        }
+    }
+}
+
+/// Signals that can be neither blocked nor handled.
+pub const UNBLOCKABLE: SignalSet = SignalSet(SignalSet::bit(SIGKILL) | SignalSet::bit(SIGSTOP));
+const STOP_SIGNALS: SignalSet = SignalSet(
+    SignalSet::bit(SIGSTOP)
+        | SignalSet::bit(SIGTSTP)
+        | SignalSet::bit(SIGTTIN)
+        | SignalSet::bit(SIGTTOU),
+);
+
+/// Disposition of a signal, as passed to `sigaction`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SignalAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of `extern "C" fn(signal: usize)`.
+    pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself.
+    pub mask: SignalSet,
+    pub flags: usize,
+    /// Where the handler returns to, it must call `sigreturn`.
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    pub const fn new() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalSet(0),
+            flags: 0,
+            restorer: 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(signal: usize) -> DefaultAction {
+    match signal {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGCONT => DefaultAction::Continue,
+        _ if STOP_SIGNALS.contains(signal) => DefaultAction::Stop,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Signal state of a process.
+pub struct SignalState {
+    pub pending: SignalSet,
+    pub blocked: SignalSet,
+    pub actions: [SignalAction; NSIG],
+    /// Stopped by SIGSTOP or alike until SIGCONT.
+    pub stopped: bool,
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalSet(0),
+            blocked: SignalSet(0),
+            actions: [SignalAction::new(); NSIG],
+            stopped: false,
+        }
+    }
+    /// State of a forked child, with the same handlers and mask but nothing pending.
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: SignalSet(0),
+            blocked: self.blocked,
+            actions: self.actions,
+            stopped: false,
+        }
+    }
+    /// Handlers point into the old program, so exec resets them. Ignored signals stay ignored.
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::new();
+            }
+        }
+    }
+    /// True if delivering `signal` would do nothing, so that it can be discarded.
+    pub fn ignores(&self, signal: usize) -> bool {
+        match self.actions[signal].handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(
+                default_action(signal),
+                DefaultAction::Ignore | DefaultAction::Continue
+            ),
+            _ => false,
+        }
+    }
+    /// Lowest pending signal that is not blocked.
+    fn next(&self) -> Option<usize> {
+        SignalSet(self.pending.0 & !self.blocked.0).first()
+    }
+    /// True if a signal waits to be delivered, blocking system calls then fail with EINTR.
+    pub fn has_pending(&self) -> bool {
+        self.next().is_some()
+    }
+}
+
+/// Saved by `handle_signals` below the user stack pointer for `sigreturn`. The handler is
+/// entered as if called from `restorer`, so it returns there.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SignalFrame {
+    restorer: usize,
+    trap_frame: TrapFrame,
+    blocked: SignalSet,
+}
+
+lazy_static! {
+    /// Tasks stopped by a signal, woken up by any SIGCONT to check whether they may go on.
+    static ref STOPPED_WAIT_QUEUE: Mutex<WaitQueue> = Mutex::new(WaitQueue::new());
+    /// The kernel thread of `keyboard_interrupt_thread`, waiting for a Ctrl-C.
+    static ref KEYBOARD_WAIT_QUEUE: Mutex<WaitQueue> = Mutex::new(WaitQueue::new());
+}
+
+/// Set by the keyboard handler on Ctrl-C, which cannot lock processes itself.
+static KEYBOARD_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Send `signal` to `process`, waking up its blocked tasks so that one of them handles it.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: usize) {
+    let mut inner = process.inner_lock();
+    if inner.is_zombie() {
+        return;
+    }
+    let mut wake = false;
+    let signals = &mut inner.signals;
+    match default_action(signal) {
+        DefaultAction::Continue => {
+            signals.pending.0 &= !STOP_SIGNALS.0;
+            if signals.stopped {
+                signals.stopped = false;
+                STOPPED_WAIT_QUEUE.lock().wake_all();
+            }
+        }
+        DefaultAction::Stop => signals.pending.remove(SIGCONT),
+        _ => {}
+    }
+    if !signals.ignores(signal) {
+        signals.pending.insert(signal);
+        wake = !signals.blocked.contains(signal);
+    }
+    if wake {
+        inner
+            .tasks
+            .iter()
+            .filter(|task| task.inner_lock().task_status == TaskStatus::Blocked)
+            .for_each(|task| wake_task(task.clone()));
+    }
+}
+
+/// True if the current task has a signal to handle or has been killed.
+pub fn signal_pending() -> bool {
+    let task = match current_task() {
+        Some(task) => task,
+        None => return false,
+    };
+    if task.inner_lock().killed {
+        return true;
+    }
+    task.process()
+        .map_or(false, |process| process.inner_lock().signals.has_pending())
+}
+
+/// Called by the keyboard handler on Ctrl-C.
+pub fn keyboard_interrupt() {
+    KEYBOARD_INTERRUPT.store(true, Ordering::SeqCst);
+    KEYBOARD_WAIT_QUEUE.lock().wake_all();
+}
+
+/// Send SIGINT for each Ctrl-C. The shell never puts processes in the background, so
+/// every process of the console session, that is every process but initproc, is in the
+/// foreground.
+fn keyboard_interrupt_thread() {
+    use crate::process::INITPROC;
+    use x86_64::instructions::interrupts;
+    loop {
+        // The keyboard handler must not set the flag between checking it and blocking.
+        interrupts::disable();
+        if !KEYBOARD_INTERRUPT.swap(false, Ordering::SeqCst) {
+            block_current_and_run_next(&KEYBOARD_WAIT_QUEUE);
+            continue;
+        }
+        interrupts::enable();
+        let mut processes = INITPROC.inner_lock().children.clone();
+        while let Some(process) = processes.pop() {
+            processes.extend(process.inner_lock().children.iter().cloned());
+            send_signal(&process, SIGINT);
+        }
+    }
+}
+
+pub fn init() {
+    crate::process::spawn_kernel_thread(keyboard_interrupt_thread);
+}
+
+fn terminate(signal: usize) -> ! {
+    let pid = current_process().unwrap().getpid();
+    println!("[kernel] Process {} killed by signal {}.", pid, signal);
+    exit_current_and_run_next(-(128 + signal as isize));
+    unreachable!("A killed process should never be scheduled again.");
+}
+
+/// Deliver the signals of the current process before the current task returns to user
+/// mode with `trap_frame`, running default actions or setting up the frame of a handler.
+/// A killed task exits instead.
+pub fn handle_signals(trap_frame: &mut TrapFrame) {
+    let task = current_task().unwrap();
+    let process = task.process().unwrap();
+    loop {
+        if task.inner_lock().killed {
+            drop(task);
+            drop(process);
+            exit_current_task(0);
+            unreachable!("A killed task should never be scheduled again.");
+        }
+        let mut inner = process.inner_lock();
+        let signal = match inner.signals.next() {
+            Some(signal) => signal,
+            None if inner.signals.stopped => {
+                drop(inner);
+                block_current_and_run_next(&STOPPED_WAIT_QUEUE);
+                continue;
+            }
+            None => return,
+        };
+        inner.signals.pending.remove(signal);
+        let action = inner.signals.actions[signal];
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signal) {
+                DefaultAction::Terminate => {
+                    drop(inner);
+                    drop(task);
+                    drop(process);
+                    terminate(signal);
+                }
+                DefaultAction::Stop => inner.signals.stopped = true,
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            },
+            handler => {
+                let blocked = inner.signals.blocked;
+                inner.signals.blocked.0 |= action.mask.0;
+                if action.flags & SA_NODEFER == 0 {
+                    inner.signals.blocked.insert(signal);
+                }
+                inner.signals.blocked.0 &= !UNBLOCKABLE.0;
+                if action.flags & SA_RESETHAND != 0 {
+                    inner.signals.actions[signal] = SignalAction::new();
+                }
+                // Accessing user memory needs the inner lock.
+                drop(inner);
+                if push_signal_frame(trap_frame, handler, action.restorer, signal, blocked).is_err() {
+                    drop(task);
+                    drop(process);
+                    terminate(SIGSEGV);
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn push_signal_frame(
+    trap_frame: &mut TrapFrame,
+    handler: usize,
+    restorer: usize,
+    signal: usize,
+    blocked: SignalSet,
+) -> Result<(), BadAddress> {
+    let frame = SignalFrame {
+        restorer,
+        trap_frame: *trap_frame,
+        blocked,
+    };
+    // The stack is 16-byte aligned before the return address is pushed by a call.
+    let sp = ((trap_frame.rsp as usize)
+        .wrapping_sub(RED_ZONE_SIZE + core::mem::size_of::<SignalFrame>())
+        & !0xf)
+        .wrapping_sub(8);
+    write_to_user(sp as *mut SignalFrame, &frame)?;
+    trap_frame.rip = handler as u64;
+    trap_frame.rsp = sp as u64;
+    trap_frame.rdi = signal as u64;
+    trap_frame.rflags &= !0x400; // DF
+    Ok(())
+}
+
+/// Restore the context saved by `push_signal_frame` once the handler has returned to the
+/// restorer, returning the saved rax which the system call returns.
+pub fn restore_signal_frame(trap_frame: &mut TrapFrame) -> Result<usize, BadAddress> {
+    // The handler's `ret` popped the restorer.
+    let frame_ptr = (trap_frame.rsp as usize).wrapping_sub(8) as *const SignalFrame;
+    let frame: SignalFrame = read_from_user(frame_ptr)?;
+    let (cs, ss) = (trap_frame.cs, trap_frame.ss);
+    *trap_frame = frame.trap_frame;
+    // Never let user space pick its privilege level or I/O privileges.
+    trap_frame.cs = cs;
+    trap_frame.ss = ss;
+    trap_frame.rflags = (trap_frame.rflags & 0xdd5) | 0x202; // Status flags, TF, DF and IF
+    let process = current_process().unwrap();
+    process.inner_lock().signals.blocked = SignalSet(frame.blocked.0 & !UNBLOCKABLE.0);
+    Ok(trap_frame.rax as usize)
+}
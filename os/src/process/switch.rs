@@ -11,7 +11,17 @@ pub fn switch_page_table(current_process: &ProcessControlBlock, target_process:
     (phys_addr.as_u64() + offset) as usize
 }
 
+/// First code run by a kernel thread, on its own kernel stack.
+#[no_mangle]
+extern "C" fn kernel_thread_main(entry: usize) {
+    let entry: fn() = unsafe { core::mem::transmute(entry) };
+    x86_64::instructions::interrupts::enable();
+    entry();
+    super::exit_current_task(0);
+}
+
 extern "C" {
+    pub fn kernel_thread_start();
     pub fn switch_mm(page_table_addr: usize);
     pub fn switch_to(
         current_task_context: *const usize,
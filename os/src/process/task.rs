@@ -0,0 +1,155 @@
+use super::{kernel_stack::KernelStack, pid::PidHandle};
+use crate::process::pcb::ProcessControlBlock;
+use crate::process::pid::alloc_pid;
+use crate::process::scheduler::SchedulingInfo;
+use crate::process::wait_queue::WaitQueue;
+use crate::system_call::TrapFrame;
+use alloc::sync::{Arc, Weak};
+use spin::{Mutex, MutexGuard};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TaskStatus {
+    Ready,
+    Running,
+    Blocked,
+    Zombie,
+}
+
+/// Registers saved by `switch_to` on the kernel stack of a task it switches away from.
+#[repr(C)]
+pub struct TaskContext {
+    r15: usize,
+    r14: usize,
+    r13: usize,
+    r12: usize,
+    r11: usize,
+    rbx: usize,
+    rbp: usize,
+    rip: usize,
+}
+
+impl TaskContext {
+    /// Start in `task_start`, which returns to user mode with the trap frame above it.
+    pub fn return_from_trap() -> Self {
+        use crate::system_call::task_start;
+        Self::new(task_start as usize, 0)
+    }
+    /// Start in `kernel_thread_start`, which calls `entry` in r12.
+    fn kernel_thread(entry: fn()) -> Self {
+        use crate::process::switch::kernel_thread_start;
+        Self::new(kernel_thread_start as usize, entry as usize)
+    }
+    fn new(rip: usize, r12: usize) -> Self {
+        Self {
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12,
+            r11: 0,
+            rbx: 0,
+            rbp: 0,
+            rip,
+        }
+    }
+}
+
+/// A thread of execution: a user thread of a process, or a kernel thread.
+pub struct TaskControlBlock {
+    /// Tids and pids are allocated together, the first task of a process has the pid as tid.
+    pub tid: usize,
+    _tid_handle: Option<PidHandle>,
+    /// None for kernel threads.
+    pub process: Option<Weak<ProcessControlBlock>>,
+    pub kernel_stack: KernelStack,
+    /// Tasks waiting in `thread_join` for this one to exit.
+    pub join_queue: Mutex<WaitQueue>,
+    inner: Mutex<TaskControlBlockInner>,
+}
+
+pub struct TaskControlBlockInner {
+    pub task_status: TaskStatus,
+    pub task_context_ptr: usize,
+    pub scheduling: SchedulingInfo,
+    pub exit_code: isize,
+    /// Set when the process is exiting or exec'ing, the task then exits instead of
+    /// returning to user mode.
+    pub killed: bool,
+    /// Slot of the user stack `thread_create` mapped for the task.
+    pub user_stack_slot: Option<usize>,
+}
+
+impl TaskControlBlock {
+    /// A user task of `process` returning to user mode with `trap_frame`. The first task
+    /// of a process uses its pid, `tid_handle` is None.
+    pub fn new_user(
+        process: &Arc<ProcessControlBlock>,
+        tid_handle: Option<PidHandle>,
+        trap_frame: TrapFrame,
+        scheduling: SchedulingInfo,
+    ) -> Arc<Self> {
+        let tid = tid_handle.as_ref().map_or(process.getpid(), |handle| handle.0);
+        let kernel_stack = KernelStack::new(tid);
+        kernel_stack.push_to_top(trap_frame, 0);
+        let task_context_ptr = kernel_stack.push_to_top(
+            TaskContext::return_from_trap(),
+            core::mem::size_of::<TrapFrame>(),
+        );
+        Arc::new(Self {
+            tid,
+            _tid_handle: tid_handle,
+            process: Some(Arc::downgrade(process)),
+            kernel_stack,
+            join_queue: Mutex::new(WaitQueue::new()),
+            inner: Mutex::new(TaskControlBlockInner::new(task_context_ptr as usize, scheduling)),
+        })
+    }
+    /// A kernel thread running `entry`, which exits when `entry` returns.
+    pub fn new_kernel(entry: fn()) -> Arc<Self> {
+        let tid_handle = alloc_pid();
+        let tid = tid_handle.0;
+        let kernel_stack = KernelStack::new(tid);
+        let task_context_ptr = kernel_stack.push_to_top(TaskContext::kernel_thread(entry), 0);
+        Arc::new(Self {
+            tid,
+            _tid_handle: Some(tid_handle),
+            process: None,
+            kernel_stack,
+            join_queue: Mutex::new(WaitQueue::new()),
+            inner: Mutex::new(TaskControlBlockInner::new(
+                task_context_ptr as usize,
+                SchedulingInfo::new(),
+            )),
+        })
+    }
+    pub fn inner_lock(&self) -> MutexGuard<TaskControlBlockInner> {
+        self.inner.lock()
+    }
+    pub fn process(&self) -> Option<Arc<ProcessControlBlock>> {
+        self.process.as_ref().and_then(|process| process.upgrade())
+    }
+    pub fn get_trap_frame(&self) -> &'static mut TrapFrame {
+        unsafe {
+            &mut *((self.kernel_stack.get_top() - core::mem::size_of::<TrapFrame>())
+                as *mut TrapFrame)
+        }
+    }
+}
+
+impl TaskControlBlockInner {
+    fn new(task_context_ptr: usize, scheduling: SchedulingInfo) -> Self {
+        Self {
+            task_status: TaskStatus::Ready,
+            task_context_ptr,
+            scheduling,
+            exit_code: 0,
+            killed: false,
+            user_stack_slot: None,
+        }
+    }
+    pub fn get_task_context_ptr2(&self) -> *const usize {
+        &self.task_context_ptr as *const usize
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.task_status == TaskStatus::Zombie
+    }
+}
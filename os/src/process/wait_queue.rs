@@ -0,0 +1,55 @@
+use crate::process::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Tasks blocked until some event happens.
+pub struct WaitQueue {
+    queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    pub fn push(&mut self, task: Arc<TaskControlBlock>) {
+        self.queue.push_back(task);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+    /// Forget `task`, which woke up for another reason than this queue.
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.queue.retain(|other| !Arc::ptr_eq(other, task));
+    }
+    pub fn wake_one(&mut self) {
+        if let Some(task) = self.queue.pop_front() {
+            wake_task(task);
+        }
+    }
+    pub fn wake_all(&mut self) {
+        while let Some(task) = self.queue.pop_front() {
+            wake_task(task);
+        }
+    }
+}
+
+lazy_static! {
+    /// Woken tasks not yet handed back to the scheduler.
+    static ref WAKE_LIST: Mutex<VecDeque<Arc<TaskControlBlock>>> = Mutex::new(VecDeque::new());
+}
+
+/// Make a blocked task ready again. Safe to call from interrupt handlers, the
+/// task only reaches the scheduler in `take_woken_tasks` from process context.
+pub fn wake_task(task: Arc<TaskControlBlock>) {
+    use x86_64::instructions::interrupts::without_interrupts;
+    without_interrupts(|| WAKE_LIST.lock().push_back(task));
+}
+
+pub fn take_woken_tasks() -> VecDeque<Arc<TaskControlBlock>> {
+    use x86_64::instructions::interrupts::without_interrupts;
+    without_interrupts(|| core::mem::take(&mut *WAKE_LIST.lock()))
+}
@@ -0,0 +1,28 @@
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::random::RdRand;
+
+lazy_static! {
+    /// None if the CPU has no RDRAND instruction.
+    static ref RDRAND: Option<RdRand> = RdRand::new();
+    /// State of the xorshift64* generator used without RDRAND, seeded from the time stamp
+    /// counter at its first use.
+    static ref STATE: Mutex<u64> = Mutex::new(unsafe { core::arch::x86_64::_rdtsc() } | 1);
+}
+
+/// A random number, not meant for cryptography.
+pub fn random_u64() -> u64 {
+    if let Some(value) = RDRAND.and_then(|rdrand| rdrand.get_u64()) {
+        return value;
+    }
+    let mut state = STATE.lock();
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+/// A random number below `bound`, which is not 0.
+pub fn random_below(bound: u64) -> u64 {
+    random_u64() % bound
+}
@@ -1,23 +1,60 @@
+pub mod error;
 mod lib;
+use error::{SysError, SysResult};
 use lib::*;
 
 #[no_mangle]
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
-    match syscall_id {
-        1 => sys_read(args[0] as *mut u8, args[1]),
-        2 => sys_write(args[0] as *const u8, args[1]),
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    let result: SysResult = match syscall_id {
+        1 => sys_read(args[0], args[1] as *mut u8, args[2]),
+        2 => sys_write(args[0], args[1] as *const u8, args[2]),
         3 => sys_exit(args[0] as isize),
         4 => sys_yield(),
         5 => sys_fork(),
-        6 => sys_exec(args[0] as *const u8),
+        6 => sys_exec(args[0] as *const u8, args[1] as _, args[2] as _),
         7 => sys_waitpid(args[0] as isize, args[1] as *mut isize),
-        _ => panic!("Unsupported system call."),
+        8 => sys_set_priority(args[0] as isize),
+        9 => sys_get_priority(),
+        10 => sys_open(args[0] as *const u8, args[1] as u32),
+        11 => sys_close(args[0]),
+        12 => sys_lseek(args[0], args[1] as isize, args[2]),
+        13 => sys_fstat(args[0], args[1] as *mut crate::fs::Stat),
+        14 => sys_dup(args[0]),
+        15 => sys_dup2(args[0], args[1]),
+        16 => sys_mkdir(args[0] as *const u8),
+        17 => sys_unlink(args[0] as *const u8),
+        18 => sys_rmdir(args[0] as *const u8),
+        19 => sys_rename(args[0] as *const u8, args[1] as *const u8),
+        20 => sys_getdents(args[0], args[1] as *mut crate::fs::Dirent, args[2]),
+        21 => sys_chdir(args[0] as *const u8),
+        22 => sys_getcwd(args[0] as *mut u8, args[1]),
+        23 => sys_pipe(args[0] as *mut usize),
+        24 => sys_kill(args[0], args[1]),
+        25 => sys_sigaction(args[0], args[1] as _, args[2] as _),
+        26 => sys_sigprocmask(args[0], args[1] as _, args[2] as _),
+        27 => sys_sigreturn(),
+        28 => sys_getpid(),
+        29 => sys_thread_create(args[0], args[1], args[2]),
+        30 => sys_gettid(),
+        31 => sys_thread_join(args[0], args[1] as *mut isize),
+        32 => sys_thread_exit(args[0] as isize),
+        33 => sys_futex(args[0] as *const u32, args[1], args[2]),
+        34 => sys_brk(args[0]),
+        35 => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        36 => sys_munmap(args[0], args[1]),
+        37 => sys_mprotect(args[0], args[1], args[2]),
+        _ => Err(SysError::ENOSYS),
+    };
+    match result {
+        Ok(value) => value as isize,
+        Err(error) => error.as_return_value(),
     }
 }
 
 pub fn trap_init() {
     use crate::gdt::{Selectors, GDT};
-    use x86_64::registers::model_specific::{Efer, EferFlags, LStar, Star};
+    use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+    use x86_64::registers::rflags::RFlags;
     let (
         _,
         Selectors {
@@ -39,12 +76,16 @@ pub fn trap_init() {
             kernel_data_seg,
         )
         .unwrap();
-        LStar::write(x86_64::VirtAddr::new(trap_start as u64))
+        LStar::write(x86_64::VirtAddr::new(trap_start as u64));
+        // Keep interrupts off until trap_start has left the user stack.
+        SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG | RFlags::DIRECTION_FLAG);
     };
 }
 
+/// User context saved on the top of the process kernel stack by `trap_start` and
+/// `timer_start`, laid out so that `trap_ret` can pop the registers and `iretq`.
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct TrapFrame {
     pub rax: u64,
     pub rbx: u64,
@@ -61,7 +102,11 @@ pub struct TrapFrame {
     pub r13: u64,
     pub r14: u64,
     pub r15: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
     pub rsp: u64,
+    pub ss: u64,
 }
 
 impl TrapFrame {
@@ -82,34 +127,91 @@ impl TrapFrame {
             r13: 0,
             r14: 0,
             r15: 0,
+            rip: 0,
+            cs: 0,
+            rflags: 0,
             rsp: 0,
+            ss: 0,
         }
     }
+
+    pub fn new_user(entry_point: usize, user_stack: usize) -> Self {
+        use crate::gdt::GDT;
+        let mut trap_frame = Self::new();
+        trap_frame.rip = entry_point as u64;
+        trap_frame.cs = GDT.1.user_code_seg.0 as u64;
+        trap_frame.rflags = 0x202; // IF
+        trap_frame.rsp = user_stack as u64;
+        trap_frame.ss = GDT.1.user_data_seg.0 as u64;
+        trap_frame
+    }
+
+    pub fn from_user_mode(&self) -> bool {
+        self.cs & 0b11 == 3
+    }
 }
 
 global_asm!(include_str!("system_call/trap.S"));
 
 extern "C" {
-    #[inline]
     fn trap_start();
-    #[inline]
+    pub fn timer_start();
     pub fn trap_ret();
+    pub fn task_start();
 }
 
+/// Kernel stack `trap_start` switches to, the same one the TSS gives to interrupts.
 #[no_mangle]
-pub fn current_kernel_stack() -> usize {
-    use crate::process::current_kernel_stack;
-    current_kernel_stack()
+static mut KERNEL_STACK_TOP: usize = 0;
+#[no_mangle]
+static mut USER_STACK_SCRATCH: usize = 0;
+
+pub fn set_kernel_stack(top: usize) {
+    unsafe { KERNEL_STACK_TOP = top };
+    crate::gdt::set_privilege_stack(top);
 }
 
 #[no_mangle]
-fn trap_syscall(trap_frame: &TrapFrame) -> isize {
-    syscall(
-        trap_frame.rax as usize, //syscall id
-        [
-            trap_frame.rdi as usize, // arg 1
-            trap_frame.rsi as usize, // arg 2
-            trap_frame.rdx as usize, // arg 3
-        ],
-    )
+fn trap_syscall(trap_frame: *mut TrapFrame) {
+    use crate::gdt::GDT;
+    // The trap frame may be rewritten by exec, so only access it through the pointer.
+    let (syscall_id, args) = unsafe {
+        (*trap_frame).cs = GDT.1.user_code_seg.0 as u64;
+        (*trap_frame).ss = GDT.1.user_data_seg.0 as u64;
+        (
+            (*trap_frame).rax as usize,
+            [
+                (*trap_frame).rdi as usize, // arg 1
+                (*trap_frame).rsi as usize, // arg 2
+                (*trap_frame).rdx as usize, // arg 3
+                (*trap_frame).r10 as usize, // arg 4, rcx holds the user rip
+                (*trap_frame).r8 as usize,  // arg 5
+                (*trap_frame).r9 as usize,  // arg 6
+            ],
+        )
+    };
+    x86_64::instructions::interrupts::enable();
+    let ret = syscall(syscall_id, args);
+    unsafe { (*trap_frame).rax = ret as u64 };
+    crate::process::signal::handle_signals(unsafe { &mut *trap_frame });
+}
+
+/// Entry of a new user task before it first returns to user mode.
+#[no_mangle]
+fn trap_task_start(trap_frame: &mut TrapFrame) {
+    crate::process::signal::handle_signals(trap_frame);
+}
+
+#[no_mangle]
+fn trap_timer(trap_frame: &mut TrapFrame) {
+    use crate::interrupts::Interrupt;
+    use crate::process::signal::handle_signals;
+    use crate::process::{suspend_current_and_run_next, timer_tick};
+    Interrupt::Timer.end_of_interrupt();
+    if trap_frame.from_user_mode() {
+        if timer_tick() {
+            suspend_current_and_run_next();
+        }
+        handle_signals(trap_frame);
+    }
 }
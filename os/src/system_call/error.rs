@@ -0,0 +1,76 @@
+use crate::fs::FsError;
+use crate::memory::elf::ElfLoadError;
+use crate::memory::user_access::BadAddress;
+
+/// Error of a system call, returned to user space as its negated errno, numbered as on Linux.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(isize)]
+pub enum SysError {
+    EPERM = 1,
+    ENOENT = 2,
+    ESRCH = 3,
+    EINTR = 4,
+    EIO = 5,
+    E2BIG = 7,
+    ENOEXEC = 8,
+    EBADF = 9,
+    ECHILD = 10,
+    EAGAIN = 11,
+    ENOMEM = 12,
+    EACCES = 13,
+    EFAULT = 14,
+    EEXIST = 17,
+    ENODEV = 19,
+    ENOTDIR = 20,
+    EISDIR = 21,
+    EINVAL = 22,
+    EMFILE = 24,
+    EFBIG = 27,
+    ENOSPC = 28,
+    ESPIPE = 29,
+    EPIPE = 32,
+    ERANGE = 34,
+    ENAMETOOLONG = 36,
+    ENOSYS = 38,
+    ENOTEMPTY = 39,
+}
+
+pub type SysResult = Result<usize, SysError>;
+
+impl SysError {
+    /// Value placed in `rax`.
+    pub fn as_return_value(self) -> isize {
+        -(self as isize)
+    }
+}
+
+impl From<FsError> for SysError {
+    fn from(error: FsError) -> Self {
+        match error {
+            FsError::NotFound => SysError::ENOENT,
+            FsError::NotDirectory => SysError::ENOTDIR,
+            FsError::IsDirectory => SysError::EISDIR,
+            FsError::AlreadyExists => SysError::EEXIST,
+            FsError::NotEmpty => SysError::ENOTEMPTY,
+            FsError::InvalidArgument => SysError::EINVAL,
+            FsError::NotSupported => SysError::ESPIPE,
+            FsError::BadDescriptor => SysError::EBADF,
+            FsError::BrokenPipe => SysError::EPIPE,
+            FsError::FileTooLarge => SysError::EFBIG,
+            FsError::NoSpace => SysError::ENOSPC,
+            FsError::Interrupted => SysError::EINTR,
+        }
+    }
+}
+
+impl From<ElfLoadError> for SysError {
+    fn from(_: ElfLoadError) -> Self {
+        SysError::ENOEXEC
+    }
+}
+
+impl From<BadAddress> for SysError {
+    fn from(_: BadAddress) -> Self {
+        SysError::EFAULT
+    }
+}
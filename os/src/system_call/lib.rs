@@ -1,36 +1,142 @@
-use crate::process::current_process;
+use super::error::{SysError, SysResult};
+use crate::fs::{absolute_path, open_file, Dirent, File, FsError, OpenFlags, SeekFrom, Stat};
+use crate::memory::user_access::{
+    check_user_range, copy_str_from_user, copy_to_user, write_to_user,
+};
+use crate::process::{current_process, current_task};
+use crate::process::signal::{SignalAction, SignalSet};
 use crate::system_call::TrapFrame;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
-pub fn sys_write(buffer: *const u8, len: usize) -> isize {
-    use crate::print;
-    let slice = unsafe { core::slice::from_raw_parts(buffer, len) };
-    let str = core::str::from_utf8(slice).unwrap();
-    print!("{}", str);
-    len as isize
+/// Largest buffer `sys_read` and `sys_write` copy from or to user space at once.
+const IO_CHUNK_SIZE: usize = 0x4000;
+
+/// Read a path from user space and resolve it against the working directory.
+fn user_path(ptr: *const u8) -> Result<String, SysError> {
+    let path = copy_str_from_user(ptr)?;
+    Ok(absolute_path(&current_process().unwrap().inner_lock().cwd, &path))
 }
 
-pub fn sys_read(buffer: *mut u8, len: usize) -> isize {
-    assert_eq!(len, 1, "Only support read len 1.");
-    use crate::interrupts::STDIN_BUFFER;
-    use crate::process::suspend_current_and_run_next;
-    use x86_64::instructions::interrupts::without_interrupts;
-    let mut c: u8 = 0;
-    loop {
-        without_interrupts(|| {
-            if let Some(ch) = STDIN_BUFFER.lock().pop() {
-                c = ch;
+fn get_file(fd: usize) -> Result<Arc<dyn File>, SysError> {
+    let proc = current_process().unwrap();
+    let file = proc.inner_lock().get_file(fd);
+    file.ok_or(SysError::EBADF)
+}
+
+/// Install `file` at the lowest free descriptor.
+fn install_file(file: Arc<dyn File>) -> SysResult {
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    let fd = inner.alloc_fd().ok_or(SysError::EMFILE)?;
+    inner.fd_table[fd] = Some(file);
+    Ok(fd)
+}
+
+pub fn sys_write(fd: usize, buffer: *const u8, len: usize) -> SysResult {
+    use crate::memory::user_access::copy_from_user;
+    let file = get_file(fd)?;
+    if !file.writable() {
+        return Err(SysError::EBADF);
+    }
+    // The buffer is checked one chunk at a time, with the inner lock released as writing
+    // may block.
+    let mut chunk = vec![0u8; len.min(IO_CHUNK_SIZE)];
+    let mut written = 0;
+    while written < len {
+        let chunk_len = (len - written).min(IO_CHUNK_SIZE);
+        if let Err(error) = copy_from_user(&mut chunk[..chunk_len], buffer.wrapping_add(written)) {
+            if written == 0 {
+                return Err(error.into());
             }
-        });
-        if c == 0 {
-            suspend_current_and_run_next();
-            continue;
-        } else {
             break;
         }
+        match file.write(&chunk[..chunk_len]) {
+            Ok(chunk_written) => {
+                written += chunk_written;
+                if chunk_written < chunk_len {
+                    break;
+                }
+            }
+            // Report the error only if nothing was written.
+            Err(FsError::BrokenPipe) if written == 0 => {
+                use crate::process::signal::{send_signal, SIGPIPE};
+                send_signal(&current_process().unwrap(), SIGPIPE);
+                return Err(SysError::EPIPE);
+            }
+            Err(error) if written == 0 => return Err(error.into()),
+            Err(_) => break,
+        }
     }
-    let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, len) };
-    buffer[0] = c;
-    1
+    Ok(written)
+}
+
+/// Read at most `IO_CHUNK_SIZE` bytes, short reads are allowed anyway.
+pub fn sys_read(fd: usize, buffer: *mut u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.readable() {
+        return Err(SysError::EBADF);
+    }
+    let len = len.min(IO_CHUNK_SIZE);
+    // Checked before reading, so that no data is consumed for a bad buffer.
+    check_user_range(buffer as usize, len, true)?;
+    let mut chunk = vec![0u8; len];
+    let read = file.read(&mut chunk)?;
+    copy_to_user(buffer, &chunk[..read])?;
+    Ok(read)
+}
+
+pub fn sys_open(path: *const u8, flags: u32) -> SysResult {
+    let path = user_path(path)?;
+    install_file(open_file(&path, OpenFlags(flags))?)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    let file = inner
+        .fd_table
+        .get_mut(fd)
+        .and_then(|file| file.take())
+        .ok_or(SysError::EBADF)?;
+    // Dropping the last reference may need the inner lock, e.g. to wake a pipe.
+    drop(inner);
+    drop(file);
+    Ok(0)
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    let file = get_file(fd)?;
+    Ok(file.seek(SeekFrom::new(offset, whence)?)?)
+}
+
+pub fn sys_fstat(fd: usize, stat_ptr: *mut Stat) -> SysResult {
+    let stat = get_file(fd)?.stat()?;
+    write_to_user(stat_ptr, &stat)?;
+    Ok(0)
+}
+
+pub fn sys_dup(fd: usize) -> SysResult {
+    install_file(get_file(fd)?)
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> SysResult {
+    use crate::process::pcb::MAX_FD;
+    let file = get_file(old_fd)?;
+    if new_fd >= MAX_FD {
+        return Err(SysError::EBADF);
+    }
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    if new_fd >= inner.fd_table.len() {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    let old_file = inner.fd_table[new_fd].replace(file);
+    drop(inner);
+    drop(old_file);
+    Ok(new_fd)
 }
 
 pub fn sys_exit(exit_code: isize) -> ! {
@@ -40,71 +146,523 @@ pub fn sys_exit(exit_code: isize) -> ! {
     exit_current_and_run_next(exit_code);
     panic!("sys_exit never returns!");
 }
-pub fn sys_fork() -> isize {
-    use crate::process::{current_process, manager::add_process};
-    let current_proc = current_process().unwrap();
-    let new_proc = current_proc.fork();
+pub fn sys_fork() -> SysResult {
+    use crate::process::manager::add_task;
+    let current_task = current_task().unwrap();
+    let new_proc = current_task
+        .process()
+        .unwrap()
+        .fork(&current_task)
+        .ok_or(SysError::ENOMEM)?;
     let new_pid = new_proc.getpid();
-    let trap_frame = new_proc.get_trap_frame();
+    let new_task = new_proc.inner_lock().tasks[0].clone();
+    let trap_frame = new_task.get_trap_frame();
     trap_frame.rax = 0; // Child process return value is 0
-    add_process(new_proc);
-    new_pid as isize
-}
-pub fn sys_exec(app_name: *const u8) -> isize {
-    use crate::loader::get_app_data_by_name;
-    use crate::process::{current_process, manager::add_process};
-    let length: usize;
-    let mut p = app_name as usize;
+    add_task(new_task);
+    Ok(new_pid)
+}
+/// Total size of the strings `sys_exec` accepts in `argv` and `envp`.
+const ARG_MAX: usize = 0x20000;
+
+/// Read a NULL-terminated array of strings from user space, a null array is empty.
+fn user_string_array(
+    array: *const *const u8,
+    total_size: &mut usize,
+) -> Result<Vec<String>, SysError> {
+    use crate::memory::user_access::read_from_user;
+    let mut strings = Vec::new();
+    if array.is_null() {
+        return Ok(strings);
+    }
     loop {
-        if unsafe { *(p as *const u8) } == 0 {
-            length = p - app_name as usize;
-            break;
+        let ptr = read_from_user(array.wrapping_add(strings.len()))?;
+        if ptr.is_null() {
+            return Ok(strings);
         }
-        p += 1;
-    }
-    let path_slice = unsafe { core::slice::from_raw_parts(app_name, length) };
-    let path = core::str::from_utf8(path_slice).unwrap();
-    if let Some(data) = get_app_data_by_name(path) {
-        let proc = current_process().unwrap();
-        proc.exec(data);
-        0
-    } else {
-        -1
+        let string = copy_str_from_user(ptr)?;
+        *total_size += string.len() + 1 + core::mem::size_of::<usize>();
+        if *total_size > ARG_MAX {
+            return Err(SysError::E2BIG);
+        }
+        strings.push(string);
+    }
+}
+
+pub fn sys_exec(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> SysResult {
+    use crate::fs::read_file;
+    use crate::memory::elf::ElfImage;
+    let path = user_path(path)?;
+    let mut total_size = 0;
+    let args = user_string_array(argv, &mut total_size)?;
+    let envs = user_string_array(envp, &mut total_size)?;
+    let data = read_file(&path)?;
+    // Checked before anything is torn down, so that a bad file is reported to the caller.
+    let elf = ElfImage::parse(&data)?;
+    let interpreter_data = match elf.interpreter {
+        Some(interpreter) => Some(read_file(interpreter)?),
+        None => None,
+    };
+    let interpreter = interpreter_data.as_deref().map(ElfImage::parse_interpreter).transpose()?;
+    let task = current_task().unwrap();
+    let proc = task.process().unwrap();
+    // Only the calling task survives, the others exit before the memory set is replaced.
+    loop {
+        use crate::process::{block_current_and_run_next, kill_task};
+        let other = proc
+            .inner_lock()
+            .tasks
+            .iter()
+            .find(|other| !Arc::ptr_eq(other, &task) && !other.inner_lock().is_zombie())
+            .cloned();
+        let other = match other {
+            Some(other) => other,
+            None => break,
+        };
+        if task.inner_lock().killed {
+            return Err(SysError::EINTR);
+        }
+        kill_task(&other);
+        block_current_and_run_next(&other.join_queue);
     }
+    proc.exec(&task, &elf, interpreter.as_ref(), &args, &envs);
+    Ok(0)
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     use crate::process::suspend_current_and_run_next;
     suspend_current_and_run_next();
-    0
+    Ok(0)
+}
+
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut isize) -> SysResult {
+    use crate::process::block_current_and_run_next;
+    use crate::process::signal::signal_pending;
+    let proc = current_process().unwrap();
+    loop {
+        let mut inner = proc.inner_lock();
+        if inner
+            .children
+            .iter()
+            .find(|p| pid == -1 || pid as usize == p.getpid())
+            .is_none()
+        {
+            return Err(SysError::ECHILD);
+        }
+        let r = inner
+            .children
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.inner_lock().is_zombie() && (pid == -1 || pid as usize == p.getpid()));
+        if let Some((idx, _)) = r {
+            let child = inner.children.remove(idx);
+            assert_eq!(Arc::strong_count(&child), 1);
+            let pid = child.getpid();
+            let exit_code = child.inner_lock().exit_code;
+            // Accessing user memory needs the inner lock.
+            drop(inner);
+            if !exit_code_ptr.is_null() && write_to_user(exit_code_ptr, &exit_code).is_err() {
+                // Leave the zombie to be reaped by a later call.
+                proc.inner_lock().children.push(child);
+                return Err(SysError::EFAULT);
+            }
+            return Ok(pid);
+        }
+        drop(inner);
+        if signal_pending() {
+            return Err(SysError::EINTR);
+        }
+        // Children only exit while this process is not running, so no wakeup is missed.
+        block_current_and_run_next(&proc.wait_queue);
+    }
+}
+
+pub fn sys_set_priority(priority: isize) -> SysResult {
+    use crate::process::scheduler::{MAX_PRIORITY, MIN_PRIORITY};
+    if priority < MIN_PRIORITY as isize || priority > MAX_PRIORITY as isize {
+        return Err(SysError::EINVAL);
+    }
+    let task = current_task().unwrap();
+    task.inner_lock().scheduling.priority = priority as usize;
+    Ok(priority as usize)
+}
+
+pub fn sys_get_priority() -> SysResult {
+    let task = current_task().unwrap();
+    let priority = task.inner_lock().scheduling.priority;
+    Ok(priority)
+}
+
+pub fn sys_mkdir(path: *const u8) -> SysResult {
+    crate::fs::make_directory(&user_path(path)?)?;
+    Ok(0)
+}
+
+pub fn sys_unlink(path: *const u8) -> SysResult {
+    crate::fs::unlink(&user_path(path)?)?;
+    Ok(0)
+}
+
+pub fn sys_rmdir(path: *const u8) -> SysResult {
+    crate::fs::remove_directory(&user_path(path)?)?;
+    Ok(0)
+}
+
+pub fn sys_rename(old_path: *const u8, new_path: *const u8) -> SysResult {
+    crate::fs::rename(&user_path(old_path)?, &user_path(new_path)?)?;
+    Ok(0)
+}
+
+pub fn sys_getdents(fd: usize, dirents: *mut Dirent, count: usize) -> SysResult {
+    let file = get_file(fd)?;
+    let size = core::mem::size_of::<Dirent>();
+    let count = count.min(IO_CHUNK_SIZE / size);
+    check_user_range(dirents as usize, count * size, true)?;
+    let mut buffer = vec![Dirent::empty(); count];
+    let count = file.getdents(&mut buffer)?;
+    let bytes = unsafe { core::slice::from_raw_parts(buffer.as_ptr() as *const u8, count * size) };
+    copy_to_user(dirents as *mut u8, bytes)?;
+    Ok(count)
+}
+
+pub fn sys_chdir(path: *const u8) -> SysResult {
+    use crate::fs::{lookup_path, InodeKind};
+    let path = user_path(path)?;
+    if lookup_path(&path)?.kind() != InodeKind::Directory {
+        return Err(SysError::ENOTDIR);
+    }
+    current_process().unwrap().inner_lock().cwd = path;
+    Ok(0)
+}
+
+/// Copy the working directory with a trailing '\0' into `buffer`, returning its length.
+pub fn sys_getcwd(buffer: *mut u8, len: usize) -> SysResult {
+    let cwd = current_process().unwrap().inner_lock().cwd.clone();
+    if cwd.len() + 1 > len {
+        return Err(SysError::ERANGE);
+    }
+    let mut bytes = cwd.into_bytes();
+    bytes.push(0);
+    copy_to_user(buffer, &bytes)?;
+    Ok(bytes.len() - 1)
+}
+
+/// Create a pipe, writing its read end and its write end to `pipe`.
+pub fn sys_pipe(pipe: *mut usize) -> SysResult {
+    use crate::fs::pipe::make_pipe;
+    let (read_end, write_end) = make_pipe();
+    let read_fd = install_file(read_end)?;
+    let write_fd = match install_file(write_end) {
+        Ok(fd) => fd,
+        Err(error) => {
+            sys_close(read_fd)?;
+            return Err(error);
+        }
+    };
+    if let Err(error) = write_to_user(pipe as *mut [usize; 2], &[read_fd, write_fd]) {
+        sys_close(read_fd)?;
+        sys_close(write_fd)?;
+        return Err(error.into());
+    }
+    Ok(0)
+}
+
+/// Send `signal` to the process `pid`, only checking that it exists if `signal` is 0.
+pub fn sys_kill(pid: usize, signal: usize) -> SysResult {
+    use crate::process::find_process;
+    use crate::process::signal::{send_signal, NSIG};
+    if signal >= NSIG {
+        return Err(SysError::EINVAL);
+    }
+    let process = find_process(pid).ok_or(SysError::ESRCH)?;
+    if signal != 0 {
+        send_signal(&process, signal);
+    }
+    Ok(0)
 }
 
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut isize) -> isize {
-    use crate::process::current_process;
+/// Set the action of `signal` unless `action` is null, writing the previous one to `old_action`.
+pub fn sys_sigaction(
+    signal: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> SysResult {
+    use crate::memory::user_access::read_from_user;
+    use crate::process::signal::{NSIG, UNBLOCKABLE};
+    if signal == 0 || signal >= NSIG {
+        return Err(SysError::EINVAL);
+    }
+    let action = if action.is_null() {
+        None
+    } else if UNBLOCKABLE.contains(signal) {
+        return Err(SysError::EINVAL);
+    } else {
+        Some(read_from_user(action)?)
+    };
     let proc = current_process().unwrap();
     let mut inner = proc.inner_lock();
-    if inner
-        .children
-        .iter()
-        .find(|p| pid == -1 || pid as usize == p.getpid())
-        .is_none()
-    {
-        return -1;
+    let old = inner.signals.actions[signal];
+    if let Some(action) = action {
+        inner.signals.actions[signal] = action;
+        // Pending signals that are now ignored are discarded.
+        if inner.signals.ignores(signal) {
+            inner.signals.pending.remove(signal);
+        }
     }
-    let r = inner
-        .children
+    drop(inner);
+    if !old_action.is_null() {
+        write_to_user(old_action, &old)?;
+    }
+    Ok(0)
+}
+
+/// Change the blocked signals as `how` says unless `set` is null, writing the previous
+/// ones to `old_set`. SIGKILL and SIGSTOP are never blocked.
+pub fn sys_sigprocmask(how: usize, set: *const SignalSet, old_set: *mut SignalSet) -> SysResult {
+    use crate::memory::user_access::read_from_user;
+    use crate::process::signal::{SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, UNBLOCKABLE};
+    let set = if set.is_null() {
+        None
+    } else {
+        Some(read_from_user(set)?)
+    };
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    let old = inner.signals.blocked;
+    if let Some(set) = set {
+        let blocked = match how {
+            SIG_BLOCK => old.0 | set.0,
+            SIG_UNBLOCK => old.0 & !set.0,
+            SIG_SETMASK => set.0,
+            _ => return Err(SysError::EINVAL),
+        };
+        inner.signals.blocked = SignalSet(blocked & !UNBLOCKABLE.0);
+    }
+    drop(inner);
+    if !old_set.is_null() {
+        write_to_user(old_set, &old)?;
+    }
+    Ok(0)
+}
+
+/// Return from a signal handler, restoring the context it interrupted.
+pub fn sys_sigreturn() -> SysResult {
+    use crate::process::signal::restore_signal_frame;
+    let trap_frame = current_task().unwrap().get_trap_frame();
+    Ok(restore_signal_frame(trap_frame)?)
+}
+
+pub fn sys_getpid() -> SysResult {
+    Ok(current_process().unwrap().getpid())
+}
+
+/// Start a thread of the current process at `entry` with `arg0` and `arg1` as its first
+/// arguments, on a new user stack, returning its tid. `ENOMEM` if there is no room left for
+/// the stack.
+pub fn sys_thread_create(entry: usize, arg0: usize, arg1: usize) -> SysResult {
+    use crate::process::manager::add_task;
+    let task = current_task().unwrap();
+    let scheduling = task.inner_lock().scheduling.clone();
+    let new_task = task
+        .process()
+        .unwrap()
+        .create_thread(entry, [arg0, arg1], scheduling)
+        .ok_or(SysError::ENOMEM)?;
+    let tid = new_task.tid;
+    add_task(new_task);
+    Ok(tid)
+}
+
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task().unwrap().tid)
+}
+
+/// Wait for the thread `tid` of the current process to exit, writing its exit code to
+/// `exit_code_ptr` unless it is null, and release it.
+pub fn sys_thread_join(tid: usize, exit_code_ptr: *mut isize) -> SysResult {
+    use crate::process::block_current_and_run_next;
+    use crate::process::signal::signal_pending;
+    let task = current_task().unwrap();
+    if tid == task.tid {
+        return Err(SysError::EINVAL);
+    }
+    let proc = task.process().unwrap();
+    let target = proc
+        .inner_lock()
+        .tasks
         .iter()
-        .enumerate()
-        .find(|(_, p)| p.inner_lock().is_zombie() && (pid == -1 || pid as usize == p.getpid()));
-    if let Some((idx, _)) = r {
-        let child = inner.children.remove(idx);
-        use alloc::sync::Arc;
-        assert_eq!(Arc::strong_count(&child), 1);
-        let pid = child.getpid();
-        let exit_code = child.inner_lock().exit_code;
-        unsafe { *exit_code_ptr = exit_code };
-        pid as isize
+        .find(|other| other.tid == tid)
+        .cloned()
+        .ok_or(SysError::ESRCH)?;
+    while !target.inner_lock().is_zombie() {
+        if signal_pending() {
+            return Err(SysError::EINTR);
+        }
+        block_current_and_run_next(&target.join_queue);
+    }
+    let exit_code = target.inner_lock().exit_code;
+    if !exit_code_ptr.is_null() {
+        // Leave the thread to be joined by a later call.
+        write_to_user(exit_code_ptr, &exit_code)?;
+    }
+    proc.inner_lock()
+        .tasks
+        .retain(|other| !Arc::ptr_eq(other, &target));
+    Ok(0)
+}
+
+/// Exit the current thread only, the process exits with `exit_code` if it was the last one.
+pub fn sys_thread_exit(exit_code: isize) -> ! {
+    use crate::process::exit_current_task;
+    exit_current_task(exit_code);
+    panic!("sys_thread_exit never returns!");
+}
+
+/// Wait on the futex word at `addr` while it holds `value`, or wake up at most `value`
+/// tasks waiting on it, returning how many.
+pub fn sys_futex(addr: *const u32, op: usize, value: usize) -> SysResult {
+    use crate::memory::user_access::{read_from_user, user_physical_address};
+    use crate::process::futex::{futex_wait, futex_wake, FUTEX_WAIT, FUTEX_WAKE};
+    use crate::process::signal::signal_pending;
+    if addr as usize % core::mem::align_of::<u32>() != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let phys_addr = user_physical_address(addr as usize)?;
+    match op {
+        FUTEX_WAIT => {
+            // Nothing can change the word between reading it and blocking.
+            if read_from_user(addr)? != value as u32 {
+                return Err(SysError::EAGAIN);
+            }
+            if signal_pending() {
+                return Err(SysError::EINTR);
+            }
+            futex_wait(phys_addr);
+            if signal_pending() {
+                return Err(SysError::EINTR);
+            }
+            Ok(0)
+        }
+        FUTEX_WAKE => Ok(futex_wake(phys_addr, value)),
+        _ => Err(SysError::EINVAL),
+    }
+}
+
+/// Move the program break to `addr` unless it is 0, returning the program break.
+pub fn sys_brk(addr: usize) -> SysResult {
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    if addr != 0 && !inner.memory_set.set_brk(addr) {
+        return Err(SysError::ENOMEM);
+    }
+    Ok(inner.memory_set.brk)
+}
+
+/// Page aligned end of the user range `addr..addr + len`, `EINVAL` if `addr` is not page
+/// aligned or the range leaves the user space.
+fn user_page_range(addr: usize, len: usize) -> SysResult {
+    use crate::memory::memory_set::{USER_END, USER_START};
+    use crate::memory::PAGE_SIZE;
+    if addr % PAGE_SIZE != 0 || addr < USER_START || len == 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = addr
+        .checked_add(len)
+        .and_then(|end| end.checked_add(PAGE_SIZE - 1))
+        .ok_or(SysError::EINVAL)?
+        & !(PAGE_SIZE - 1);
+    if end > USER_END {
+        return Err(SysError::EINVAL);
+    }
+    Ok(end)
+}
+
+/// Map `len` bytes, anonymous or from `fd` at `offset`, near `addr` or exactly there with
+/// `MAP_FIXED`, returning where.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    use crate::fs::InodeKind;
+    use crate::memory::memory_set::*;
+    use crate::memory::PAGE_SIZE;
+    use x86_64::VirtAddr;
+    let page_flags = prot_flags(prot).ok_or(SysError::EINVAL)?;
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return Err(SysError::EINVAL),
+    };
+    if len == 0 || offset % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let backing = if flags & MAP_ANONYMOUS != 0 {
+        None
     } else {
-        -2
+        let file = get_file(fd)?;
+        let inode = file.inode().ok_or(SysError::ENODEV)?;
+        if inode.kind() == InodeKind::Directory {
+            return Err(SysError::ENODEV);
+        }
+        if !file.readable() || (shared && prot & PROT_WRITE != 0 && !file.writable()) {
+            return Err(SysError::EACCES);
+        }
+        Some(FileBacking { inode, offset })
+    };
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    let start = if flags & MAP_FIXED != 0 {
+        let end = user_page_range(addr, len)?;
+        inner
+            .memory_set
+            .unmap_range(VirtAddr::new(addr as u64), VirtAddr::new(end as u64));
+        VirtAddr::new(addr as u64)
+    } else {
+        let len = len.checked_add(PAGE_SIZE - 1).ok_or(SysError::ENOMEM)? & !(PAGE_SIZE - 1);
+        // A hint that is no address at all is ignored like one that is taken.
+        let hint = if addr != 0 { VirtAddr::try_new(addr as u64).ok() } else { None };
+        inner
+            .memory_set
+            .find_free_range(len, hint)
+            .ok_or(SysError::ENOMEM)?
+    };
+    if !inner
+        .memory_set
+        .map_region(start, len, page_flags, shared, backing)
+    {
+        return Err(SysError::ENOMEM);
+    }
+    Ok(start.as_u64() as usize)
+}
+
+/// Unmap the pages of `addr..addr + len`, mapped or not.
+pub fn sys_munmap(addr: usize, len: usize) -> SysResult {
+    use x86_64::VirtAddr;
+    let end = user_page_range(addr, len)?;
+    let proc = current_process().unwrap();
+    proc.inner_lock()
+        .memory_set
+        .unmap_range(VirtAddr::new(addr as u64), VirtAddr::new(end as u64));
+    Ok(0)
+}
+
+/// Change the protection of the pages of `addr..addr + len`, `ENOMEM` if some are not
+/// mapped.
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> SysResult {
+    use crate::memory::memory_set::prot_flags;
+    use x86_64::VirtAddr;
+    let end = user_page_range(addr, len)?;
+    let flags = prot_flags(prot).ok_or(SysError::EINVAL)?;
+    let proc = current_process().unwrap();
+    let mut inner = proc.inner_lock();
+    if !inner
+        .memory_set
+        .protect_range(VirtAddr::new(addr as u64), VirtAddr::new(end as u64), flags)
+    {
+        return Err(SysError::ENOMEM);
     }
+    Ok(0)
 }
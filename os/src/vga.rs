@@ -1,3 +1,4 @@
+use crate::memory::PHYSICAL_MEMORY_OFFSET;
 use core::fmt;
 use core::ops::{Deref, DerefMut};
 use lazy_static::lazy_static;
@@ -96,8 +97,13 @@ impl Writer {
     }
 
     pub fn write_string(&mut self, s: &str) {
-        s.as_bytes().iter().for_each(|&byte| match byte {
-            0x20..0x7e | b'\n' => self.write_byte(byte),
+        self.write_bytes(s.as_bytes())
+    }
+
+    /// Write bytes which are not necessarily UTF-8, replacing unprintable ones with '■'.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| match byte {
+            0x20..=0x7e | b'\n' => self.write_byte(byte),
             _ => self.write_byte(0xfe),
         })
     }
@@ -137,7 +143,7 @@ lazy_static! {
         column_position: 0,
         row_position: 0,
         color_code: ColorCode::default(),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        buffer: unsafe { &mut *((PHYSICAL_MEMORY_OFFSET + 0xb8000) as *mut Buffer) },
     });
 }
 
@@ -0,0 +1,65 @@
+use crate::sbrk;
+use crate::sync::Mutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use linked_list_allocator::Heap;
+
+/// Least the heap grows by, to make few `brk` calls.
+const HEAP_GROWTH: usize = 64 * 1024;
+
+/// Heap on the memory above the initial program break. It moves the program break itself,
+/// so programs using it must not call `brk` or `sbrk`.
+struct UserHeap {
+    heap: Mutex<Heap>,
+}
+
+impl UserHeap {
+    /// Add at least `size` bytes to the heap, false once `brk` fails.
+    fn grow(heap: &mut Heap, size: usize) -> bool {
+        let size = size.max(HEAP_GROWTH);
+        let bottom = match sbrk(size as isize) {
+            Ok(bottom) => bottom,
+            Err(_) => return false,
+        };
+        unsafe {
+            if heap.size() == 0 {
+                heap.init(bottom, size);
+            } else {
+                assert_eq!(bottom, heap.top(), "The program break was moved.");
+                heap.extend(size);
+            }
+        }
+        true
+    }
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.heap.lock();
+        if let Ok(ptr) = heap.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+        // The new memory follows the free memory at the top of the heap, if any.
+        if !Self::grow(&mut heap, layout.size() + layout.align()) {
+            return null_mut();
+        }
+        heap.allocate_first_fit(layout)
+            .map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+}
+
+#[global_allocator]
+static HEAP: UserHeap = UserHeap {
+    heap: Mutex::new(Heap::empty()),
+};
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("Heap allocation of {} bytes failed.", layout.size());
+}
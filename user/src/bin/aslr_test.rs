@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::open_flags::O_RDONLY;
+use user_lib::*;
+
+const RUNS: usize = 4;
+
+static STATIC: u8 = 0;
+
+/// Addresses of the code, data, heap and stack of a new process.
+type Layout = [usize; 4];
+
+fn layout() -> Layout {
+    let local = 0u8;
+    [main as usize, &STATIC as *const u8 as usize, brk(0).unwrap(), &local as *const u8 as usize]
+}
+
+/// Layout of this program run again by exec.
+fn exec_layout() -> Layout {
+    let (read_end, write_end) = pipe().unwrap();
+    let pid = fork().unwrap();
+    if pid == 0 {
+        dup2(write_end, STDOUT).unwrap();
+        exec("/bin/aslr_test\0", &["aslr_test", "child"]).unwrap();
+    }
+    close(write_end).unwrap();
+    let mut layout = [0usize; 4];
+    let bytes = unsafe { core::slice::from_raw_parts_mut(layout.as_mut_ptr() as *mut u8, 32) };
+    let mut received = 0;
+    while received < bytes.len() {
+        let len = read(read_end, &mut bytes[received..]).unwrap();
+        assert!(len > 0);
+        received += len;
+    }
+    close(read_end).unwrap();
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    layout
+}
+
+/// Whether the kernel was booted with randomization disabled.
+fn norandmaps() -> bool {
+    let fd = match open("/etc/cmdline\0", O_RDONLY) {
+        Ok(fd) => fd,
+        Err(_) => return false,
+    };
+    let mut buffer = [0u8; 256];
+    let len = read(fd, &mut buffer).unwrap_or(0);
+    close(fd).unwrap();
+    core::str::from_utf8(&buffer[..len])
+        .map_or(false, |cmdline| cmdline.split_whitespace().any(|option| option == "norandmaps"))
+}
+
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "child" {
+        let layout = layout();
+        let bytes = unsafe { core::slice::from_raw_parts(layout.as_ptr() as *const u8, 32) };
+        write(STDOUT, bytes).unwrap();
+        return 0;
+    }
+    let layouts: [Layout; RUNS] = [exec_layout(), exec_layout(), exec_layout(), exec_layout()];
+    if norandmaps() {
+        assert!(layouts.iter().all(|layout| layout == &layouts[0]));
+        println!("aslr_test passed, randomization is disabled.");
+        return 0;
+    }
+    // Each part moves, the odds that it stays in place for every run are negligible.
+    for part in 0..4 {
+        assert!(layouts.iter().any(|layout| layout[part] != layouts[0][part]));
+    }
+    println!("aslr_test passed!");
+    0
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+/// The kernel has to reject pointers outside of this process' memory instead of crashing.
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let null = unsafe { core::slice::from_raw_parts(core::ptr::null::<u8>(), 16) };
+    assert_eq!(write(STDOUT, null), Err(SysError::EFAULT));
+    // Mapped in every address space, but only for the kernel.
+    let kernel = unsafe { core::slice::from_raw_parts_mut(0x5000000 as *mut u8, 16) };
+    assert_eq!(read(STDIN, kernel), Err(SysError::EFAULT));
+    let text = unsafe { core::slice::from_raw_parts_mut(main as usize as *mut u8, 16) };
+    assert_eq!(read(STDIN, text), Err(SysError::EFAULT));
+    assert_eq!(open(unsafe { core::str::from_utf8_unchecked(null) }, 0), Err(SysError::EFAULT));
+    let exit_code = unsafe { &mut *(0x10 as *mut isize) };
+    if fork() == Ok(0) {
+        exit(0);
+    }
+    assert_eq!(wait(exit_code), Err(SysError::EFAULT));
+    let mut exit_code = 0;
+    assert!(wait(&mut exit_code).is_ok());
+    assert_eq!(wait(&mut exit_code), Err(SysError::ECHILD));
+    println!("bad_pointer passed!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::open_flags::O_RDONLY;
+use user_lib::{close, open, read, write, SysResult, STDIN, STDOUT};
+
+/// Copy `fd` to the standard output until its end.
+fn copy_to_stdout(fd: usize) -> SysResult<()> {
+    let mut buffer = [0u8; 256];
+    loop {
+        match read(fd, &mut buffer)? {
+            0 => return Ok(()),
+            len => {
+                write(STDOUT, &buffer[..len])?;
+            }
+        }
+    }
+}
+
+/// Print the files named by the arguments, or the standard input without any.
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        return if copy_to_stdout(STDIN).is_ok() { 0 } else { -1 };
+    }
+    let mut exit_code = 0;
+    for path in argv[1..].iter() {
+        let result = open(path, O_RDONLY).and_then(|fd| {
+            let result = copy_to_stdout(fd);
+            close(fd)?;
+            result
+        });
+        if let Err(error) = result {
+            println!("cat: {}: {}", path, error);
+            exit_code = -1;
+        }
+    }
+    exit_code
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::open_flags::*;
+use user_lib::*;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    assert_eq!(mkdir("/tmp_test\0"), Ok(()));
+    assert_eq!(mkdir("/tmp_test\0"), Err(SysError::EEXIST));
+    assert_eq!(chdir("/tmp_test\0"), Ok(()));
+
+    let fd = open("hello.txt\0", O_CREAT | O_WRONLY).unwrap();
+    let text = "Hello, tmpfs!";
+    assert_eq!(write(fd, text.as_bytes()), Ok(text.len()));
+    close(fd).unwrap();
+
+    assert_eq!(rename("hello.txt\0", "../tmp_test/./renamed.txt\0"), Ok(()));
+    assert_eq!(open("hello.txt\0", O_RDONLY), Err(SysError::ENOENT));
+    let fd = open("/tmp_test/renamed.txt\0", O_RDONLY).unwrap();
+    let mut stat = Stat::default();
+    assert_eq!(fstat(fd, &mut stat), Ok(()));
+    assert_eq!(stat.mode & S_IFMT, S_IFREG);
+    assert_eq!(stat.size, text.len() as u64);
+    let mut buffer = [0u8; 32];
+    let len = read(fd, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], text.as_bytes());
+    assert_eq!(write(fd, text.as_bytes()), Err(SysError::EBADF));
+    close(fd).unwrap();
+    assert_eq!(close(fd), Err(SysError::EBADF));
+
+    let mut cwd = [0u8; 64];
+    let len = getcwd(&mut cwd).unwrap();
+    assert_eq!(&cwd[..len], b"/tmp_test");
+
+    let fd = open(".\0", O_RDONLY).unwrap();
+    let mut dirents = [Dirent::empty(); 4];
+    assert_eq!(getdents(fd, &mut dirents), Ok(1));
+    assert_eq!(dirents[0].name(), "renamed.txt");
+    assert!(!dirents[0].is_dir());
+    assert_eq!(getdents(fd, &mut dirents), Ok(0));
+    close(fd).unwrap();
+
+    assert_eq!(rmdir("/tmp_test\0"), Err(SysError::ENOTEMPTY));
+    assert_eq!(unlink("renamed.txt\0"), Ok(()));
+    assert_eq!(chdir("..\0"), Ok(()));
+    assert_eq!(rmdir("tmp_test\0"), Ok(()));
+
+    // Files hold arbitrary bytes, read back in a single call.
+    let binary: [u8; 8] = [0x00, 0xff, 0x80, 0xc3, 0x28, b'\n', 0x00, 0x7f];
+    let fd = open("/binary\0", O_CREAT | O_RDWR | O_TRUNC).unwrap();
+    assert_eq!(write(fd, &binary), Ok(binary.len()));
+    assert_eq!(lseek(fd, 0, SEEK_SET), Ok(0));
+    assert_eq!(read(fd, &mut buffer), Ok(binary.len()));
+    assert_eq!(&buffer[..binary.len()], &binary);
+    close(fd).unwrap();
+    // Not an executable, exec fails and this process goes on.
+    assert_eq!(exec("/binary\0", &["/binary"]), Err(SysError::ENOEXEC));
+    unlink("/binary\0").unwrap();
+
+    // Shipped by the initramfs.
+    let fd = open("/etc/motd\0", O_RDONLY).unwrap();
+    assert!(read(fd, &mut buffer).unwrap() > 0);
+    close(fd).unwrap();
+    println!("fs_test passed!");
+    0
+}
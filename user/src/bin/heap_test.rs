@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use user_lib::*;
+
+/// Larger than the initial growth of the heap.
+const LARGE_SIZE: usize = 1024 * 1024;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut numbers: Vec<usize> = (0..1000).collect();
+    numbers.retain(|n| n % 3 == 0);
+    assert_eq!(numbers.iter().sum::<usize>(), 166833);
+
+    let mut words = String::new();
+    for n in 0..100 {
+        words += &n.to_string();
+    }
+    assert_eq!(words.len(), 190);
+
+    let boxed = Box::new([7u8; 4096]);
+    assert!(boxed.iter().all(|&b| b == 7));
+
+    let mut map = BTreeMap::new();
+    for n in 0..100usize {
+        map.insert(n * n % 97, n);
+    }
+    // 0 and the 48 quadratic residues modulo 97, the last square wins.
+    assert_eq!(map.len(), 49);
+    assert_eq!(map.get(&0), Some(&97));
+
+    let large = alloc::vec![1u64; LARGE_SIZE / 8];
+    assert_eq!(large.iter().sum::<u64>(), (LARGE_SIZE / 8) as u64);
+    drop(large);
+
+    // The program break can not go below the heap.
+    let brk_now = brk(0).unwrap();
+    assert_eq!(brk(1), Err(SysError::ENOMEM));
+    assert_eq!(brk(0), Ok(brk_now));
+
+    println!("heap_test passed!");
+    0
+}
@@ -5,7 +5,7 @@
 extern crate user_lib;
 
 #[no_mangle]
-unsafe fn main() -> i32 {
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
     // write("Hello World!\n".as_bytes());
     println!("Hello World!");
     0
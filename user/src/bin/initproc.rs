@@ -7,17 +7,21 @@ extern crate user_lib;
 use user_lib::{fork, exec, yield_, wait};
 
 #[no_mangle]
-unsafe fn main() -> i32 {
-    if fork() == 0 {
-        exec("user_shell\0");
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    if fork() == Ok(0) {
+        if let Err(error) = exec("/bin/user_shell", &["user_shell"]) {
+            println!("[initproc] Cannot execute the shell: {}", error);
+        }
     } else {
         loop {
             let mut exit_code: isize = 0;
-            let pid = wait(&mut exit_code);
-            if pid == -1 || pid == -2 {
-                yield_();
-                continue;
-            }
+            let pid = match wait(&mut exit_code) {
+                Ok(pid) => pid,
+                Err(_) => {
+                    yield_();
+                    continue;
+                }
+            };
             println!("[initproc] Released a zombie process, pid={}, exit_code={}",
                      pid, exit_code)
         }
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::mman::*;
+use user_lib::signal::SIGSEGV;
+use user_lib::*;
+
+const FRAME_SIZE: usize = 4096;
+
+/// Larger than the memory of the machine if it was allocated at once.
+const HUGE_SIZE: usize = 1 << 30;
+
+static mut BSS: [u8; 4 * 1024 * 1024] = [0; 4 * 1024 * 1024];
+
+/// Use about `depth` stack frames of `FRAME_SIZE` bytes each.
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; FRAME_SIZE];
+    unsafe { core::ptr::write_volatile(&mut frame[depth % FRAME_SIZE], depth as u8) };
+    if depth == 0 {
+        return 0;
+    }
+    let sum = recurse(depth - 1);
+    sum + unsafe { core::ptr::read_volatile(&frame[depth % FRAME_SIZE]) } as usize
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // BSS pages are zeroed on their first access.
+    unsafe {
+        for i in (0..BSS.len()).step_by(256 * 1024) {
+            assert_eq!(core::ptr::read_volatile(&BSS[i]), 0);
+            BSS[i] = 1;
+        }
+        assert_eq!(BSS.iter().step_by(256 * 1024).filter(|&&b| b == 1).count(), 16);
+    }
+
+    // The stack grows past its initial megabyte.
+    let depth = 1024;
+    let expected: usize = (1..=depth).map(|d| d % 256).sum();
+    assert_eq!(recurse(depth), expected);
+
+    // Until it reaches its guard page.
+    let pid = fork().unwrap();
+    if pid == 0 {
+        recurse(usize::MAX);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -(128 + SIGSEGV as isize));
+
+    // Only the pages touched take frames.
+    let addr = mmap(0, HUGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0).unwrap();
+    for offset in (0..HUGE_SIZE).step_by(HUGE_SIZE / 16) {
+        let byte = (addr + offset) as *mut u8;
+        unsafe {
+            assert_eq!(byte.read_volatile(), 0);
+            byte.write_volatile(1);
+        }
+    }
+    munmap(addr, HUGE_SIZE).unwrap();
+
+    println!("lazy_test passed!");
+    0
+}
@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::mman::*;
+use user_lib::open_flags::*;
+use user_lib::signal::SIGSEGV;
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+
+unsafe fn bytes(addr: usize, len: usize) -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(addr as *mut u8, len)
+}
+
+/// Exit code of a child running `f`.
+fn in_child(f: impl FnOnce()) -> isize {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    exit_code
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // Anonymous private mappings are zeroed and copied by fork.
+    let len = 4 * PAGE_SIZE;
+    let addr = mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0).unwrap();
+    let private = unsafe { bytes(addr, len) };
+    assert!(private.iter().all(|&b| b == 0));
+    private.fill(1);
+    assert_eq!(in_child(|| private.fill(2)), 0);
+    assert!(private.iter().all(|&b| b == 1));
+
+    // Unmapping the middle pages leaves the others in place.
+    munmap(addr + PAGE_SIZE, 2 * PAGE_SIZE).unwrap();
+    assert_eq!(private[0], 1);
+    assert_eq!(private[len - 1], 1);
+    assert_eq!(in_child(|| private[PAGE_SIZE] = 0), -(128 + SIGSEGV as isize));
+    let again = mmap(addr + PAGE_SIZE, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0);
+    assert_eq!(again, Ok(addr + PAGE_SIZE));
+    munmap(addr, len).unwrap();
+
+    // Shared mappings are not copied by fork.
+    let addr = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, 0, 0).unwrap();
+    let shared = unsafe { bytes(addr, PAGE_SIZE) };
+    assert_eq!(in_child(|| shared.fill(3)), 0);
+    assert!(shared.iter().all(|&b| b == 3));
+
+    // A read-only page can not be written, even by a child.
+    mprotect(addr, PAGE_SIZE, PROT_READ).unwrap();
+    assert_eq!(in_child(|| shared[0] = 4), -(128 + SIGSEGV as isize));
+    assert_eq!(shared[0], 3);
+    mprotect(addr, PAGE_SIZE, PROT_READ | PROT_WRITE).unwrap();
+    shared[0] = 4;
+    munmap(addr, PAGE_SIZE).unwrap();
+    assert_eq!(mprotect(addr, PAGE_SIZE, PROT_READ), Err(SysError::ENOMEM));
+
+    // File mappings read the file, shared ones write it back.
+    let fd = open("mmap.txt\0", O_CREAT | O_RDWR | O_TRUNC).unwrap();
+    write(fd, b"hello, mmap").unwrap();
+    let addr = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, fd, 0).unwrap();
+    let file = unsafe { bytes(addr, PAGE_SIZE) };
+    assert_eq!(&file[..11], b"hello, mmap");
+    assert!(file[11..].iter().all(|&b| b == 0));
+    munmap(addr, PAGE_SIZE).unwrap();
+    let addr = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0).unwrap();
+    let file = unsafe { bytes(addr, PAGE_SIZE) };
+    file[..5].copy_from_slice(b"HELLO");
+    munmap(addr, PAGE_SIZE).unwrap();
+    let mut buffer = [0u8; 16];
+    lseek(fd, 0, SEEK_SET).unwrap();
+    assert_eq!(read(fd, &mut buffer), Ok(11));
+    assert_eq!(&buffer[..11], b"HELLO, mmap");
+    close(fd).unwrap();
+
+    let fd = open("mmap.txt\0", O_RDONLY).unwrap();
+    let result = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert_eq!(result, Err(SysError::EACCES));
+    close(fd).unwrap();
+    unlink("mmap.txt\0").unwrap();
+    let result = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, STDIN, 0);
+    assert_eq!(result, Err(SysError::ENODEV));
+    let result = mmap(0, 0, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0);
+    assert_eq!(result, Err(SysError::EINVAL));
+
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::*;
+
+const MESSAGE: &str = "Hello through a pipe!";
+const LARGE_SIZE: usize = 10000;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let (read_end, write_end) = pipe().unwrap();
+    if fork() == Ok(0) {
+        close(read_end).unwrap();
+        assert_eq!(write(write_end, MESSAGE.as_bytes()), Ok(MESSAGE.len()));
+        // Larger than the pipe buffer, so the writer has to wait for the reader.
+        let large = [b'x'; LARGE_SIZE];
+        assert_eq!(write(write_end, &large), Ok(LARGE_SIZE));
+        close(write_end).unwrap();
+        exit(0);
+    }
+    close(write_end).unwrap();
+    let mut buffer = [0u8; 64];
+    let mut received = 0;
+    while received < MESSAGE.len() {
+        let len = read(read_end, &mut buffer[received..MESSAGE.len()]).unwrap();
+        assert!(len > 0);
+        received += len;
+    }
+    assert_eq!(&buffer[..received], MESSAGE.as_bytes());
+    let mut total = 0;
+    loop {
+        let len = read(read_end, &mut buffer).unwrap();
+        if len == 0 {
+            break;
+        }
+        total += len;
+    }
+    assert_eq!(total, LARGE_SIZE);
+    let mut exit_code = 0;
+    wait(&mut exit_code).unwrap();
+    assert_eq!(exit_code, 0);
+    close(read_end).unwrap();
+
+    // Writing without any reader fails.
+    let (read_end, write_end) = pipe().unwrap();
+    close(read_end).unwrap();
+    assert_eq!(write(write_end, MESSAGE.as_bytes()), Err(SysError::EPIPE));
+    close(write_end).unwrap();
+    println!("pipe_test passed!");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_priority, set_priority, wait};
+
+const ROUNDS: usize = 5;
+const LOOPS_PER_ROUND: usize = 2_000_000;
+
+fn spin() {
+    let mut x = 0usize;
+    for i in 0..LOOPS_PER_ROUND {
+        x = unsafe { core::ptr::read_volatile(&i) }.wrapping_add(x);
+    }
+}
+
+#[no_mangle]
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    for &priority in [4, 8, 16].iter() {
+        if fork() == Ok(0) {
+            set_priority(priority).unwrap();
+            for round in 0..ROUNDS {
+                spin();
+                println!("[priority {}] round {}", get_priority().unwrap(), round);
+            }
+            exit(0);
+        }
+    }
+    for _ in 0..3 {
+        let mut exit_code: isize = 0;
+        wait(&mut exit_code).unwrap();
+    }
+    0
+}
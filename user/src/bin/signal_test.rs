@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::signal::*;
+use user_lib::*;
+
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(signal: usize) {
+    RECEIVED.store(signal, Ordering::SeqCst);
+}
+
+/// Fork a child spinning forever, send it `signals` and return its exit code.
+fn kill_spinning_child(signals: &[usize]) -> isize {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        loop {}
+    }
+    for &signal in signals.iter() {
+        kill(pid, signal).unwrap();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    exit_code
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let pid = getpid();
+
+    // The handler runs before kill returns, which keeps its return value.
+    sigaction(SIGUSR1, &SignalAction::handler(handler)).unwrap();
+    assert_eq!(kill(pid, SIGUSR1), Ok(()));
+    assert_eq!(RECEIVED.swap(0, Ordering::SeqCst), SIGUSR1);
+
+    // Blocked signals stay pending until they are unblocked.
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR1)).unwrap();
+    kill(pid, SIGUSR1).unwrap();
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), 0);
+    assert_eq!(sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1)), Ok(sigmask(SIGUSR1)));
+    assert_eq!(RECEIVED.swap(0, Ordering::SeqCst), SIGUSR1);
+
+    // Ignored signals are discarded.
+    signal(SIGUSR2, SIG_IGN).unwrap();
+    kill(pid, SIGUSR2).unwrap();
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), 0);
+
+    assert_eq!(kill(pid, NSIG), Err(SysError::EINVAL));
+    assert_eq!(signal(SIGKILL, SIG_IGN), Err(SysError::EINVAL));
+    assert_eq!(kill(usize::MAX, SIGTERM), Err(SysError::ESRCH));
+
+    // Default actions.
+    assert_eq!(kill_spinning_child(&[SIGTERM]), -(128 + SIGTERM as isize));
+    assert_eq!(
+        kill_spinning_child(&[SIGSTOP, SIGCONT, SIGINT]),
+        -(128 + SIGINT as isize)
+    );
+    assert_eq!(kill_spinning_child(&[SIGSTOP, SIGKILL]), -(128 + SIGKILL as isize));
+
+    println!("signal_test passed!");
+    0
+}
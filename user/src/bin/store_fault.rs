@@ -0,0 +1,12 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+#[no_mangle]
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    println!("Writing to a null pointer, the kernel should kill this process.");
+    (0usize as *mut u8).write_volatile(0);
+    0
+}
@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::sync::{Condvar, Mutex, Once, Semaphore};
+use user_lib::thread::*;
+use user_lib::*;
+
+const THREADS: usize = 4;
+const INCREMENTS: usize = 200;
+const ITEMS: usize = 100;
+const SLOTS: usize = 4;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+
+/// Increment `COUNTER` without an atomic operation, yielding inside the critical section
+/// so that the other threads contend for the lock.
+fn increment(_: usize) -> i32 {
+    for _ in 0..INCREMENTS {
+        let mut counter = COUNTER.lock();
+        let value = *counter;
+        yield_();
+        *counter = value + 1;
+    }
+    0
+}
+
+/// A bounded buffer of `SLOTS` items, the semaphores count free and used slots.
+struct Ring {
+    items: [usize; SLOTS],
+    head: usize,
+    tail: usize,
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring { items: [0; SLOTS], head: 0, tail: 0 });
+static FREE_SLOTS: Semaphore = Semaphore::new(SLOTS as u32);
+static USED_SLOTS: Semaphore = Semaphore::new(0);
+
+fn produce(_: usize) -> i32 {
+    for item in 0..ITEMS {
+        FREE_SLOTS.wait();
+        let mut ring = RING.lock();
+        let tail = ring.tail;
+        ring.items[tail % SLOTS] = item;
+        ring.tail += 1;
+        drop(ring);
+        USED_SLOTS.post();
+    }
+    0
+}
+
+fn consume(_: usize) -> i32 {
+    let mut sum = 0;
+    for _ in 0..ITEMS {
+        USED_SLOTS.wait();
+        let mut ring = RING.lock();
+        let head = ring.head;
+        sum += ring.items[head % SLOTS];
+        ring.head += 1;
+        drop(ring);
+        FREE_SLOTS.post();
+    }
+    sum as i32
+}
+
+static FINISHED: Mutex<usize> = Mutex::new(0);
+static ALL_FINISHED: Condvar = Condvar::new();
+static INIT: Once = Once::new();
+static INIT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn finish(_: usize) -> i32 {
+    INIT.call_once(|| {
+        // The other threads must wait for the initialization to end.
+        yield_();
+        INIT_CALLS.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(INIT_CALLS.load(Ordering::SeqCst), 1);
+    let mut finished = FINISHED.lock();
+    *finished += 1;
+    if *finished == THREADS {
+        ALL_FINISHED.notify_all();
+    }
+    0
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut tids = [0; THREADS];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(increment, 0).unwrap();
+    }
+    for &tid in tids.iter() {
+        assert_eq!(thread_join(tid), Ok(0));
+    }
+    assert_eq!(*COUNTER.lock(), THREADS * INCREMENTS);
+    println!("Mutex passed.");
+
+    let consumer = thread_create(consume, 0).unwrap();
+    let producer = thread_create(produce, 0).unwrap();
+    assert_eq!(thread_join(producer), Ok(0));
+    assert_eq!(thread_join(consumer), Ok((ITEMS * (ITEMS - 1) / 2) as isize));
+    println!("Semaphore passed.");
+
+    for tid in tids.iter_mut() {
+        *tid = thread_create(finish, 0).unwrap();
+    }
+    let mut finished = FINISHED.lock();
+    while *finished < THREADS {
+        finished = ALL_FINISHED.wait(finished);
+    }
+    drop(finished);
+    for &tid in tids.iter() {
+        assert_eq!(thread_join(tid), Ok(0));
+    }
+    assert!(INIT.is_completed());
+    assert_eq!(INIT_CALLS.load(Ordering::SeqCst), 1);
+    println!("Condvar and Once passed.");
+
+    println!("sync_test passed!");
+    0
+}
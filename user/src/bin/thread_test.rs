@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::thread::*;
+use user_lib::*;
+
+const THREADS: usize = 4;
+const INCREMENTS: usize = 1000;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn increment(id: usize) -> i32 {
+    for i in 0..INCREMENTS {
+        COUNTER.fetch_add(1, Ordering::SeqCst);
+        if i % 100 == 0 {
+            yield_();
+        }
+    }
+    id as i32
+}
+
+fn spin(_: usize) -> i32 {
+    loop {}
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // Threads share the memory of the process.
+    let mut tids = [0; THREADS];
+    for (id, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(increment, id).unwrap();
+        assert_ne!(*tid, gettid());
+    }
+    for (id, &tid) in tids.iter().enumerate() {
+        assert_eq!(thread_join(tid), Ok(id as isize));
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS * INCREMENTS);
+    assert_eq!(thread_join(tids[0]), Err(SysError::ESRCH));
+    assert_eq!(thread_join(gettid()), Err(SysError::EINVAL));
+
+    // exit ends every thread of the process.
+    let pid = fork().unwrap();
+    if pid == 0 {
+        thread_create(spin, 0).unwrap();
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 7);
+
+    // Otherwise the process exits with its last thread.
+    let pid = fork().unwrap();
+    if pid == 0 {
+        thread_create(increment, 5).unwrap();
+        thread_exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 5);
+
+    println!("thread_test passed!");
+    0
+}
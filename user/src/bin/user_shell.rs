@@ -3,46 +3,121 @@
 
 #[macro_use]
 extern crate user_lib;
+extern crate alloc;
 
-use user_lib::{fork, exec, yield_, waitpid};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{chdir, close, dup2, exec, exit, fork, getcwd, pipe, waitpid, STDIN, STDOUT};
 use user_lib::console::getchar;
+use user_lib::signal::{signal, SIGINT, SIG_DFL, SIG_IGN};
 
 const LF: u8 = '\n' as u8;
 
+/// Resolve `command` into a path, commands without a directory are looked up in /bin.
+fn command_path(command: &str) -> String {
+    if command.contains('/') {
+        String::from(command)
+    } else {
+        format!("/bin/{}", command)
+    }
+}
+
+/// Run `command`, split into arguments on whitespace, in place of this process.
+fn exec_command(command: &str) -> ! {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    if args.is_empty() {
+        exit(0);
+    }
+    let error = exec(&command_path(args[0]), &args).unwrap_err();
+    println!("{}: {}", args[0], error);
+    exit(-1);
+}
+
+/// Run the commands of `line` separated by '|', each reading the output of the previous one.
+fn run_pipeline(line: &str) {
+    let mut pids = Vec::new();
+    let mut input: Option<usize> = None;
+    let mut commands = line.split('|').map(|command| command.trim()).peekable();
+    while let Some(command) = commands.next() {
+        let output = if commands.peek().is_some() {
+            match pipe() {
+                Ok(ends) => Some(ends),
+                Err(error) => {
+                    println!("[shell] Cannot create a pipe: {}", error);
+                    break;
+                }
+            }
+        } else {
+            None
+        };
+        let pid = fork().unwrap();
+        if pid == 0 {
+            if let Some(input) = input {
+                dup2(input, STDIN).unwrap();
+                close(input).unwrap();
+            }
+            if let Some((read_end, write_end)) = output {
+                dup2(write_end, STDOUT).unwrap();
+                close(read_end).unwrap();
+                close(write_end).unwrap();
+            }
+            // Ctrl-C interrupts the commands, not the shell.
+            signal(SIGINT, SIG_DFL).unwrap();
+            exec_command(command);
+        }
+        // Only the children use the pipe ends, so that readers see the end of their input.
+        if let Some(input) = input {
+            close(input).unwrap();
+        }
+        input = output.map(|(read_end, write_end)| {
+            close(write_end).unwrap();
+            read_end
+        });
+        pids.push(pid);
+    }
+    if let Some(input) = input {
+        close(input).unwrap();
+    }
+    for pid in pids {
+        let mut exit_code: isize = 0;
+        let exit_pid = waitpid(pid, &mut exit_code).unwrap();
+        assert_eq!(pid, exit_pid);
+        println!("[shell] Process {} exited with code {}.", pid, exit_code);
+    }
+}
+
 #[no_mangle]
-unsafe fn main() -> i32 {
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    signal(SIGINT, SIG_IGN).unwrap();
     print!(">> ");
-    let mut line = [0u8; 256];
-    let mut len = 0;
+    let mut line: Vec<u8> = Vec::new();
     loop {
-        let mut c = getchar();
+        let c = getchar();
         match c {
             LF => {
                 println!("");
-                line[len] = 0;
-                let path = core::str::from_utf8(&line[..=len]).unwrap();
-                let pid = fork();
-                if pid == 0 {
-                    if exec(path) == -1 {
-                        println!("Error when executing!");
-                        return -1;
-                    };
-                    unreachable!();
-                } else {
-                    let mut exit_code: isize = 0;
-                    let exit_pid = waitpid(pid as usize, &mut exit_code);
-                    assert_eq!(pid, exit_pid);
-                    println!("[shell] Process {} exited with code {}.", pid, exit_code);
+                let line = String::from_utf8_lossy(&core::mem::take(&mut line)).into_owned();
+                // The working directory belongs to the shell, so these can not run in a child.
+                if line == "pwd" {
+                    let mut cwd = [0u8; 256];
+                    match getcwd(&mut cwd) {
+                        Ok(cwd_len) => println!("{}", core::str::from_utf8(&cwd[..cwd_len]).unwrap()),
+                        Err(error) => println!("pwd: {}", error),
+                    }
+                } else if line.starts_with("cd ") {
+                    if let Err(error) = chdir(&line[3..]) {
+                        println!("cd: {}", error);
+                    }
+                } else if !line.is_empty() {
+                    run_pipeline(&line);
                 }
-                len = 0;
                 print!(">> ");
             }
             _ => {
-                line[len] = c;
-                len += 1;
+                line.push(c);
                 print!("{}", c as char)
             }
         }
     }
-    0
 }
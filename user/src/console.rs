@@ -1,16 +1,16 @@
-use super::{write, read};
+use super::{write, read, SysError, STDIN, STDOUT};
 use core::fmt::{self, Write};
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(s.as_bytes());
-        Ok(())
+        write(STDOUT, s.as_bytes()).map(|_| ()).map_err(|_| fmt::Error)
     }
 }
 
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    // Nothing can report a failure to write to stdout.
+    let _ = Stdout.write_fmt(args);
 }
 
 #[macro_export]
@@ -29,6 +29,14 @@ macro_rules! println {
 
 pub fn getchar() -> u8 {
     let mut buffer = [0u8; 1];
-    read(&mut buffer);
-    buffer[0]
+    loop {
+        match read(STDIN, &mut buffer) {
+            // Interrupted by a signal handler.
+            Err(SysError::EINTR) => continue,
+            result => {
+                result.expect("Read from stdin failed.");
+                return buffer[0];
+            }
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use core::fmt;
+
+/// Error of a system call, the errno the kernel returned negated.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct SysError(pub usize);
+
+pub type SysResult<T = usize> = Result<T, SysError>;
+
+macro_rules! errors {
+    ($($name: ident = $errno: expr, $description: expr;)*) => {
+        impl SysError {
+            $(pub const $name: SysError = SysError($errno);)*
+
+            pub fn name(self) -> &'static str {
+                match self.0 {
+                    $($errno => stringify!($name),)*
+                    _ => "EUNKNOWN",
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self.0 {
+                    $($errno => $description,)*
+                    _ => "Unknown error",
+                }
+            }
+        }
+    };
+}
+
+errors! {
+    EPERM = 1, "Operation not permitted";
+    ENOENT = 2, "No such file or directory";
+    ESRCH = 3, "No such process";
+    EINTR = 4, "Interrupted system call";
+    EIO = 5, "I/O error";
+    E2BIG = 7, "Argument list too long";
+    ENOEXEC = 8, "Exec format error";
+    EBADF = 9, "Bad file descriptor";
+    ECHILD = 10, "No child processes";
+    EAGAIN = 11, "Resource temporarily unavailable";
+    ENOMEM = 12, "Out of memory";
+    EACCES = 13, "Permission denied";
+    EFAULT = 14, "Bad address";
+    EEXIST = 17, "File exists";
+    ENODEV = 19, "No such device";
+    ENOTDIR = 20, "Not a directory";
+    EISDIR = 21, "Is a directory";
+    EINVAL = 22, "Invalid argument";
+    EMFILE = 24, "Too many open files";
+    EFBIG = 27, "File too large";
+    ENOSPC = 28, "No space left on device";
+    ESPIPE = 29, "Illegal seek";
+    EPIPE = 32, "Broken pipe";
+    ERANGE = 34, "Result too large";
+    ENAMETOOLONG = 36, "File name too long";
+    ENOSYS = 38, "Function not implemented";
+    ENOTEMPTY = 39, "Directory not empty";
+}
+
+impl SysError {
+    /// Split the raw return value of a system call.
+    pub fn from_return(ret: isize) -> SysResult {
+        if ret < 0 {
+            Err(SysError((-ret) as usize))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
+impl fmt::Debug for SysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for SysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.description(), self.name())
+    }
+}
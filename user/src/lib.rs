@@ -1,61 +1,298 @@
 #![feature(asm)]
+#![feature(naked_functions)]
 #![feature(linkage)]
 #![no_std]
 #![feature(panic_info_message)]
 #![feature(global_asm)]
+#![feature(alloc_error_handler)]
 
+extern crate alloc;
+
+mod allocator;
 mod syscall;
 pub mod console;
+pub mod error;
+pub mod signal;
+pub mod sync;
+pub mod thread;
+
+/// Most arguments `main` receives, the others are dropped.
+pub const MAX_ARGS: usize = 64;
 
+// The kernel enters with argc, argv, envp and auxv on the stack, see the System V x86-64 ABI.
+// Programs do not link their own, /lib/ld.so jumps here once libuser_lib.so is loaded.
+#[naked]
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
-    exit(main());
-    panic!("unreachable after sys_exit!");
+pub unsafe extern "C" fn _start() -> ! {
+    asm!(
+        "mov rdi, rsp",
+        "and rsp, -16",
+        "call rust_start",
+        options(noreturn),
+    );
+}
+
+static mut ENVP: *const *const u8 = core::ptr::null();
+
+/// Borrow a NUL-terminated string without its NUL.
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+#[no_mangle]
+unsafe extern "C" fn rust_start(stack: *const usize) -> ! {
+    let argc = *stack;
+    let argv = stack.add(1) as *const *const u8;
+    ENVP = argv.add(argc + 1);
+    let mut args = [""; MAX_ARGS];
+    for (i, arg) in args.iter_mut().enumerate().take(argc) {
+        *arg = c_str(*argv.add(i));
+    }
+    exit(main(argc, &args[..argc.min(MAX_ARGS)]));
 }
 
 #[linkage = "weak"]
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     panic!("Cannot find main!");
 }
 
+/// Value of the environment variable `name`.
+pub fn getenv(name: &str) -> Option<&'static str> {
+    let mut envp = unsafe { ENVP };
+    while !envp.is_null() && unsafe { !(*envp).is_null() } {
+        let env = unsafe { c_str(*envp) };
+        if env.len() > name.len() && env.starts_with(name) && env.as_bytes()[name.len()] == b'=' {
+            return Some(&env[name.len() + 1..]);
+        }
+        envp = unsafe { envp.add(1) };
+    }
+    None
+}
+
 use console::*;
 use syscall::*;
+pub use error::{SysError, SysResult};
 
-pub fn write(buffer: &[u8]) -> isize {
-    sys_write(buffer)
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// Flags of `open`.
+pub mod open_flags {
+    pub const O_RDONLY: u32 = 0;
+    pub const O_WRONLY: u32 = 1 << 0;
+    pub const O_RDWR: u32 = 1 << 1;
+    pub const O_CREAT: u32 = 1 << 6;
+    pub const O_TRUNC: u32 = 1 << 9;
+    pub const O_APPEND: u32 = 1 << 10;
 }
 
-pub fn exit(exit_code: i32) -> isize {
-    sys_exit(exit_code)
+/// Protections and flags of `mmap`.
+pub mod mman {
+    pub const PROT_NONE: usize = 0;
+    pub const PROT_READ: usize = 1 << 0;
+    pub const PROT_WRITE: usize = 1 << 1;
+    pub const PROT_EXEC: usize = 1 << 2;
+    pub const MAP_SHARED: usize = 0x01;
+    pub const MAP_PRIVATE: usize = 0x02;
+    pub const MAP_FIXED: usize = 0x10;
+    pub const MAP_ANONYMOUS: usize = 0x20;
 }
 
-pub fn fork() -> isize { sys_fork() }
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
 
-pub fn exec(path: &str) -> isize { sys_exec(path) }
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Stat {
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub size: u64,
+}
 
-pub fn yield_() -> isize { sys_yield() }
+pub const NAME_MAX: usize = 255;
 
-pub fn wait(exit_code_ptr: &mut isize) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code_ptr as *mut isize) {
-            -2 => { yield_(); }
-            pid => return pid
-        }
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Dirent {
+    pub ino: u64,
+    /// File type, `mode >> 12` of `Stat`.
+    pub kind: u8,
+    pub name: [u8; NAME_MAX + 1],
+}
+
+impl Dirent {
+    pub const fn empty() -> Self {
+        Self { ino: 0, kind: 0, name: [0; NAME_MAX + 1] }
+    }
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(NAME_MAX);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+    pub fn is_dir(&self) -> bool {
+        (self.kind as u32) << 12 == S_IFDIR
     }
 }
 
-pub fn waitpid(pid: usize, exit_code_ptr: &mut isize) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code_ptr) {
-            -2 => { yield_(); }
-            pid => return pid
+pub fn write(fd: usize, buffer: &[u8]) -> SysResult {
+    SysError::from_return(sys_write(fd, buffer))
+}
+
+pub fn exit(exit_code: i32) -> ! {
+    sys_exit(exit_code);
+    unreachable!("sys_exit never returns!");
+}
+
+/// Returns 0 in the child and the pid of the child in the parent.
+pub fn fork() -> SysResult { SysError::from_return(sys_fork()) }
+
+/// Size of the buffer `execve` copies its strings to, with their '\0' added.
+const EXEC_STRINGS_SIZE: usize = 4096;
+
+/// Copy `strings` terminated by '\0' into `buffer`, returning a NULL-terminated pointer array.
+fn c_str_array(
+    strings: &[&str],
+    buffer: &mut [u8],
+    pointers: &mut [*const u8; MAX_ARGS + 1],
+) -> SysResult<()> {
+    if strings.len() > MAX_ARGS {
+        return Err(SysError::E2BIG);
+    }
+    let mut offset = 0;
+    for (string, pointer) in strings.iter().zip(pointers.iter_mut()) {
+        let bytes = string.trim_end_matches('\0').as_bytes();
+        let end = offset + bytes.len() + 1;
+        if end > buffer.len() {
+            return Err(SysError::E2BIG);
         }
+        buffer[offset..end - 1].copy_from_slice(bytes);
+        buffer[end - 1] = 0;
+        *pointer = buffer[offset..].as_ptr();
+        offset = end;
+    }
+    pointers[strings.len()] = core::ptr::null();
+    Ok(())
+}
+
+/// Run `path` with the arguments `args`, conventionally starting with the program name,
+/// and the environment `envs` of "NAME=value" strings. Only returns on failure.
+pub fn execve(path: &str, args: &[&str], envs: &[&str]) -> SysResult {
+    let mut arg_buffer = [0u8; EXEC_STRINGS_SIZE];
+    let mut env_buffer = [0u8; EXEC_STRINGS_SIZE];
+    let mut argv = [core::ptr::null(); MAX_ARGS + 1];
+    let mut envp = [core::ptr::null(); MAX_ARGS + 1];
+    c_str_array(args, &mut arg_buffer, &mut argv)?;
+    c_str_array(envs, &mut env_buffer, &mut envp)?;
+    SysError::from_return(sys_exec(path, argv.as_ptr(), envp.as_ptr()))
+}
+
+/// `execve` keeping the environment of this process.
+pub fn exec(path: &str, args: &[&str]) -> SysResult {
+    let mut arg_buffer = [0u8; EXEC_STRINGS_SIZE];
+    let mut argv = [core::ptr::null(); MAX_ARGS + 1];
+    c_str_array(args, &mut arg_buffer, &mut argv)?;
+    SysError::from_return(sys_exec(path, argv.as_ptr(), unsafe { ENVP }))
+}
+
+pub fn yield_() { sys_yield(); }
+
+/// Block until a child exits, returning its pid, ECHILD if there is no child.
+pub fn wait(exit_code: &mut isize) -> SysResult {
+    SysError::from_return(sys_waitpid(-1, exit_code as *mut isize))
+}
+
+pub fn waitpid(pid: usize, exit_code: &mut isize) -> SysResult {
+    SysError::from_return(sys_waitpid(pid as isize, exit_code as *mut isize))
+}
+
+pub fn read(fd: usize, buffer: &mut [u8]) -> SysResult { SysError::from_return(sys_read(fd, buffer)) }
+
+pub fn open(path: &str, flags: u32) -> SysResult { SysError::from_return(sys_open(path, flags)) }
+
+pub fn close(fd: usize) -> SysResult<()> { SysError::from_return(sys_close(fd)).map(|_| ()) }
+
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    SysError::from_return(sys_lseek(fd, offset, whence))
+}
+
+pub fn fstat(fd: usize, stat: &mut Stat) -> SysResult<()> {
+    SysError::from_return(sys_fstat(fd, stat as *mut Stat)).map(|_| ())
+}
+
+pub fn dup(fd: usize) -> SysResult { SysError::from_return(sys_dup(fd)) }
+
+pub fn dup2(old_fd: usize, new_fd: usize) -> SysResult { SysError::from_return(sys_dup2(old_fd, new_fd)) }
+
+pub fn mkdir(path: &str) -> SysResult<()> { SysError::from_return(sys_mkdir(path)).map(|_| ()) }
+
+pub fn unlink(path: &str) -> SysResult<()> { SysError::from_return(sys_unlink(path)).map(|_| ()) }
+
+pub fn rmdir(path: &str) -> SysResult<()> { SysError::from_return(sys_rmdir(path)).map(|_| ()) }
+
+pub fn rename(old_path: &str, new_path: &str) -> SysResult<()> {
+    SysError::from_return(sys_rename(old_path, new_path)).map(|_| ())
+}
+
+/// Read the next entries of the directory `fd`, 0 at its end.
+pub fn getdents(fd: usize, dirents: &mut [Dirent]) -> SysResult {
+    SysError::from_return(sys_getdents(fd, dirents))
+}
+
+/// Create a pipe, returning its read end and its write end.
+pub fn pipe() -> SysResult<(usize, usize)> {
+    let mut fds = [0usize; 2];
+    SysError::from_return(sys_pipe(&mut fds)).map(|_| (fds[0], fds[1]))
+}
+
+pub fn chdir(path: &str) -> SysResult<()> { SysError::from_return(sys_chdir(path)).map(|_| ()) }
+
+/// Write the working directory with a trailing '\0' into `buffer`, returning its length.
+pub fn getcwd(buffer: &mut [u8]) -> SysResult { SysError::from_return(sys_getcwd(buffer)) }
+
+pub fn set_priority(priority: isize) -> SysResult { SysError::from_return(sys_set_priority(priority)) }
+
+pub fn get_priority() -> SysResult { SysError::from_return(sys_get_priority()) }
+
+pub fn getpid() -> usize { sys_getpid() as usize }
+
+/// Move the program break to `addr`, returning it. `brk(0)` only returns it.
+pub fn brk(addr: usize) -> SysResult { SysError::from_return(sys_brk(addr)) }
+
+/// Move the program break by `increment`, returning the previous one.
+pub fn sbrk(increment: isize) -> SysResult {
+    let old_brk = brk(0)?;
+    if increment != 0 {
+        brk((old_brk as isize + increment) as usize)?;
     }
+    Ok(old_brk)
+}
+
+/// Map `len` bytes of `fd` from `offset`, or zeroed ones with `MAP_ANONYMOUS`, at `addr`
+/// if it is free or with `MAP_FIXED`, returning where.
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> SysResult {
+    SysError::from_return(sys_mmap(addr, len, prot, flags, fd, offset))
+}
+
+pub fn munmap(addr: usize, len: usize) -> SysResult<()> {
+    SysError::from_return(sys_munmap(addr, len)).map(|_| ())
 }
 
-pub fn read(buffer: &mut [u8]) -> isize { sys_read(buffer) }
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> SysResult<()> {
+    SysError::from_return(sys_mprotect(addr, len, prot)).map(|_| ())
+}
 
 #[panic_handler]
 fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
@@ -65,5 +302,5 @@ fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
     } else {
         println!("Panicked: {}", err);
     }
-    loop {}
+    exit(-1);
 }
@@ -0,0 +1,100 @@
+use crate::syscall::*;
+use crate::{SysError, SysResult};
+
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// Do not block the signal while its handler runs.
+pub const SA_NODEFER: usize = 0x4000_0000;
+/// Reset the handler to `SIG_DFL` once the signal is delivered.
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Set of signals as `sigprocmask` takes it.
+pub const fn sigmask(signal: usize) -> u64 {
+    1 << (signal - 1)
+}
+
+/// A signal handler, called with the signal number.
+pub type SignalHandler = extern "C" fn(usize);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SignalAction {
+    /// `SIG_DFL`, `SIG_IGN` or a `SignalHandler`.
+    pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself.
+    pub mask: u64,
+    pub flags: usize,
+    /// Set by `sigaction`.
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    pub const fn new(handler: usize) -> Self {
+        Self { handler, mask: 0, flags: 0, restorer: 0 }
+    }
+    pub fn handler(handler: SignalHandler) -> Self {
+        Self::new(handler as usize)
+    }
+}
+
+// Handlers return here, see `SystemCall::SysSigreturn`.
+global_asm!("\
+.globl __restore_rt
+__restore_rt:
+    movq $27, %rax
+    syscall
+");
+
+extern "C" {
+    fn __restore_rt();
+}
+
+pub fn kill(pid: usize, signal: usize) -> SysResult<()> {
+    SysError::from_return(sys_kill(pid, signal)).map(|_| ())
+}
+
+/// Set the action of `signal`, returning the previous one.
+pub fn sigaction(signal: usize, action: &SignalAction) -> SysResult<SignalAction> {
+    let mut action = *action;
+    action.restorer = __restore_rt as usize;
+    let mut old_action = SignalAction::new(SIG_DFL);
+    SysError::from_return(sys_sigaction(signal, &action, &mut old_action)).map(|_| old_action)
+}
+
+/// Set the handler of `signal` to `handler`, `SIG_DFL` or `SIG_IGN`, returning the previous one.
+pub fn signal(signal: usize, handler: usize) -> SysResult<usize> {
+    sigaction(signal, &SignalAction::new(handler)).map(|old_action| old_action.handler)
+}
+
+/// Change the blocked signals as `how` says, returning the previous ones.
+pub fn sigprocmask(how: usize, set: u64) -> SysResult<u64> {
+    let mut old_set = 0;
+    SysError::from_return(sys_sigprocmask(how, &set, &mut old_set)).map(|_| old_set)
+}
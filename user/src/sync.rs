@@ -0,0 +1,209 @@
+use crate::syscall::*;
+use crate::{SysError, SysResult};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/// Block while `futex` holds `value`. Returns early with `EAGAIN` if it does not, and
+/// with `EINTR` if a signal arrives, callers check their condition again anyway.
+pub fn futex_wait(futex: &AtomicU32, value: u32) -> SysResult<()> {
+    let futex = futex as *const AtomicU32 as *const u32;
+    SysError::from_return(sys_futex(futex, FUTEX_WAIT, value as usize)).map(|_| ())
+}
+
+/// Wake up at most `count` threads blocked on `futex`, returning how many.
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> usize {
+    let futex = futex as *const AtomicU32 as *const u32;
+    sys_futex(futex, FUTEX_WAKE, count).max(0) as usize
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked with threads possibly blocked in `lock`.
+const CONTENDED: u32 = 2;
+
+/// A lock sleeping in the kernel while it is held, it only makes a system call when
+/// there is contention.
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                let _ = futex_wait(&self.state, CONTENDED);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Threads waiting for a condition protected by a `Mutex`. Wakeups may be spurious, so
+/// `wait` is called in a loop checking the condition.
+pub struct Condvar {
+    /// Bumped by each notification, so that one sent after `wait` released the mutex is
+    /// not missed.
+    sequence: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            sequence: AtomicU32::new(0),
+        }
+    }
+    /// Release the mutex of `guard` until notified, then lock it again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let _ = futex_wait(&self.sequence, sequence);
+        mutex.lock()
+    }
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, 1);
+    }
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, usize::MAX);
+    }
+}
+
+/// A counting semaphore.
+pub struct Semaphore {
+    count: AtomicU32,
+    /// Threads about to block in `wait`, `post` makes no system call without them.
+    waiters: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(count: u32) -> Self {
+        Self {
+            count: AtomicU32::new(count),
+            waiters: AtomicU32::new(0),
+        }
+    }
+    /// Take one unit, blocking until there is one.
+    pub fn wait(&self) {
+        loop {
+            let count = self.count.load(Ordering::Relaxed);
+            if count > 0 {
+                if self
+                    .count
+                    .compare_exchange(count, count - 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return;
+                }
+                continue;
+            }
+            self.waiters.fetch_add(1, Ordering::SeqCst);
+            let _ = futex_wait(&self.count, 0);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+    /// Take one unit if there is one.
+    pub fn try_wait(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| count.checked_sub(1))
+            .is_ok()
+    }
+    /// Give back one unit.
+    pub fn post(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            futex_wake(&self.count, 1);
+        }
+    }
+}
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Runs an initialization once, the other callers wait until it is done.
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+        if self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+        {
+            f();
+            self.state.store(COMPLETE, Ordering::Release);
+            futex_wake(&self.state, usize::MAX);
+            return;
+        }
+        while self.state.load(Ordering::Acquire) == RUNNING {
+            let _ = futex_wait(&self.state, RUNNING);
+        }
+    }
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
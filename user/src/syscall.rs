@@ -1,3 +1,22 @@
+use crate::signal::SignalAction;
+use crate::{Dirent, SysError, Stat};
+
+pub const PATH_MAX: usize = 4096;
+
+/// Call `f` with `string` terminated by '\0' as the kernel expects, copying it unless
+/// it already ends with one.
+pub fn with_c_str(string: &str, f: impl FnOnce(*const u8) -> isize) -> isize {
+    if string.ends_with('\0') {
+        return f(string.as_ptr());
+    }
+    if string.len() >= PATH_MAX {
+        return -(SysError::ENAMETOOLONG.0 as isize);
+    }
+    let mut buffer = [0u8; PATH_MAX];
+    buffer[..string.len()].copy_from_slice(string.as_bytes());
+    f(buffer.as_ptr())
+}
+
 #[repr(usize)]
 pub enum SystemCall {
     SysRead = 1,
@@ -7,6 +26,37 @@ pub enum SystemCall {
     SysFork,
     SysExec,
     SysWaitPID,
+    SysSetPriority,
+    SysGetPriority,
+    SysOpen,
+    SysClose,
+    SysLseek,
+    SysFstat,
+    SysDup,
+    SysDup2,
+    SysMkdir,
+    SysUnlink,
+    SysRmdir,
+    SysRename,
+    SysGetdents,
+    SysChdir,
+    SysGetcwd,
+    SysPipe,
+    SysKill,
+    SysSigaction,
+    SysSigprocmask,
+    #[allow(dead_code)] // Only made by `signal::__restore_rt`.
+    SysSigreturn,
+    SysGetPID,
+    SysThreadCreate,
+    SysGettid,
+    SysThreadJoin,
+    SysThreadExit,
+    SysFutex,
+    SysBrk,
+    SysMmap,
+    SysMunmap,
+    SysMprotect,
 }
 
 impl SystemCall {
@@ -18,19 +68,19 @@ impl SystemCall {
     }
 }
 
-pub fn sys_read(buffer: &mut [u8]) -> isize {
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     unsafe {
-        system_call(SystemCall::SysRead, buffer.as_mut_ptr() as usize, buffer.len(), 0)
+        system_call(SystemCall::SysRead, fd, buffer.as_mut_ptr() as usize, buffer.len())
     }
 }
 
 
 
-pub fn sys_write(buffer: &[u8]) -> isize {
+pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     unsafe {
         system_call(
             SystemCall::SysWrite,
-            buffer.as_ptr() as usize, buffer.len(), 0,
+            fd, buffer.as_ptr() as usize, buffer.len(),
         )
     }
 }
@@ -45,14 +95,144 @@ pub fn sys_fork() -> isize {
     unsafe { system_call(SystemCall::SysFork, 0, 0, 0) }
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    unsafe { system_call(SystemCall::SysExec, path.as_ptr() as usize, 0, 0) }
+pub fn sys_exec(path: &str, argv: *const *const u8, envp: *const *const u8) -> isize {
+    with_c_str(path, |path| unsafe {
+        system_call(SystemCall::SysExec, path as usize, argv as usize, envp as usize)
+    })
 }
 
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut isize) -> isize {
     unsafe { system_call(SystemCall::SysWaitPID, pid as usize, exit_code_ptr as usize, 0) }
 }
 
+pub fn sys_set_priority(priority: isize) -> isize {
+    unsafe { system_call(SystemCall::SysSetPriority, priority as usize, 0, 0) }
+}
+
+pub fn sys_get_priority() -> isize {
+    unsafe { system_call(SystemCall::SysGetPriority, 0, 0, 0) }
+}
+
+pub fn sys_open(path: &str, flags: u32) -> isize {
+    with_c_str(path, |path| unsafe {
+        system_call(SystemCall::SysOpen, path as usize, flags as usize, 0)
+    })
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    unsafe { system_call(SystemCall::SysClose, fd, 0, 0) }
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    unsafe { system_call(SystemCall::SysLseek, fd, offset as usize, whence) }
+}
+
+pub fn sys_fstat(fd: usize, stat: *mut Stat) -> isize {
+    unsafe { system_call(SystemCall::SysFstat, fd, stat as usize, 0) }
+}
+
+pub fn sys_dup(fd: usize) -> isize {
+    unsafe { system_call(SystemCall::SysDup, fd, 0, 0) }
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    unsafe { system_call(SystemCall::SysDup2, old_fd, new_fd, 0) }
+}
+
+pub fn sys_mkdir(path: &str) -> isize {
+    with_c_str(path, |path| unsafe { system_call(SystemCall::SysMkdir, path as usize, 0, 0) })
+}
+
+pub fn sys_unlink(path: &str) -> isize {
+    with_c_str(path, |path| unsafe { system_call(SystemCall::SysUnlink, path as usize, 0, 0) })
+}
+
+pub fn sys_rmdir(path: &str) -> isize {
+    with_c_str(path, |path| unsafe { system_call(SystemCall::SysRmdir, path as usize, 0, 0) })
+}
+
+pub fn sys_rename(old_path: &str, new_path: &str) -> isize {
+    with_c_str(old_path, |old_path| with_c_str(new_path, |new_path| unsafe {
+        system_call(SystemCall::SysRename, old_path as usize, new_path as usize, 0)
+    }))
+}
+
+pub fn sys_getdents(fd: usize, dirents: &mut [Dirent]) -> isize {
+    unsafe {
+        system_call(SystemCall::SysGetdents, fd, dirents.as_mut_ptr() as usize, dirents.len())
+    }
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    with_c_str(path, |path| unsafe { system_call(SystemCall::SysChdir, path as usize, 0, 0) })
+}
+
+pub fn sys_pipe(pipe: &mut [usize; 2]) -> isize {
+    unsafe { system_call(SystemCall::SysPipe, pipe.as_mut_ptr() as usize, 0, 0) }
+}
+
+pub fn sys_getcwd(buffer: &mut [u8]) -> isize {
+    unsafe { system_call(SystemCall::SysGetcwd, buffer.as_mut_ptr() as usize, buffer.len(), 0) }
+}
+
+pub fn sys_kill(pid: usize, signal: usize) -> isize {
+    unsafe { system_call(SystemCall::SysKill, pid, signal, 0) }
+}
+
+pub fn sys_sigaction(
+    signal: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    unsafe {
+        system_call(SystemCall::SysSigaction, signal, action as usize, old_action as usize)
+    }
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> isize {
+    unsafe { system_call(SystemCall::SysSigprocmask, how, set as usize, old_set as usize) }
+}
+
+pub fn sys_getpid() -> isize {
+    unsafe { system_call(SystemCall::SysGetPID, 0, 0, 0) }
+}
+
+pub fn sys_thread_create(entry: usize, arg0: usize, arg1: usize) -> isize {
+    unsafe { system_call(SystemCall::SysThreadCreate, entry, arg0, arg1) }
+}
+
+pub fn sys_gettid() -> isize {
+    unsafe { system_call(SystemCall::SysGettid, 0, 0, 0) }
+}
+
+pub fn sys_thread_join(tid: usize, exit_code: *mut isize) -> isize {
+    unsafe { system_call(SystemCall::SysThreadJoin, tid, exit_code as usize, 0) }
+}
+
+pub fn sys_thread_exit(exit_code: i32) -> isize {
+    unsafe { system_call(SystemCall::SysThreadExit, exit_code as usize, 0, 0) }
+}
+
+pub fn sys_futex(addr: *const u32, op: usize, value: usize) -> isize {
+    unsafe { system_call(SystemCall::SysFutex, addr as usize, op, value) }
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    unsafe { system_call(SystemCall::SysBrk, addr, 0, 0) }
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    unsafe { system_call6(SystemCall::SysMmap, addr, len, prot, flags, fd, offset) }
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    unsafe { system_call(SystemCall::SysMunmap, addr, len, 0) }
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    unsafe { system_call(SystemCall::SysMprotect, addr, len, prot) }
+}
+
 
 
 global_asm!("\
@@ -67,6 +247,22 @@ system_call:
     retq
 ");
 
+// The kernel takes the fourth argument in %r10 since syscall overwrites %rcx, the sixth
+// one is passed on the stack past the return address.
+global_asm!("\
+.globl system_call6
+system_call6:
+    movq %rdi, %rax
+    movq %rsi, %rdi
+    movq %rdx, %rsi
+    movq %rcx, %rdx
+    movq %r8, %r10
+    movq %r9, %r8
+    movq 8(%rsp), %r9
+    syscall
+    retq
+");
+
 /*
     movl (%rsp), %ecx
     addq $0x4, %rsp
@@ -75,6 +271,15 @@ system_call:
 
 extern {
     fn system_call(syscall_id: SystemCall, arg0: usize, arg1: usize, arg2: usize) -> isize;
+    fn system_call6(
+        syscall_id: SystemCall,
+        arg0: usize,
+        arg1: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> isize;
 }
 
 /*
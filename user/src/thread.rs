@@ -0,0 +1,35 @@
+use crate::syscall::*;
+use crate::{SysError, SysResult};
+
+/// First code run by a thread, exiting it with what `f` returns.
+extern "C" fn thread_start(f: usize, arg: usize) -> ! {
+    let f: fn(usize) -> i32 = unsafe { core::mem::transmute(f) };
+    thread_exit(f(arg))
+}
+
+/// Run `f(arg)` in a new thread of the process, returning its tid.
+pub fn thread_create(f: fn(usize) -> i32, arg: usize) -> SysResult {
+    SysError::from_return(sys_thread_create(thread_start as usize, f as usize, arg))
+}
+
+pub fn gettid() -> usize {
+    sys_gettid() as usize
+}
+
+/// Wait for the thread `tid` to exit, returning its exit code.
+pub fn thread_join(tid: usize) -> SysResult<isize> {
+    let mut exit_code = 0;
+    loop {
+        match SysError::from_return(sys_thread_join(tid, &mut exit_code)) {
+            // Interrupted by a signal whose handler has run.
+            Err(SysError::EINTR) => continue,
+            result => return result.map(|_| exit_code),
+        }
+    }
+}
+
+/// Exit the current thread, the process exits once its last thread has.
+pub fn thread_exit(exit_code: i32) -> ! {
+    sys_thread_exit(exit_code);
+    unreachable!("sys_thread_exit never returns!");
+}